@@ -1,41 +1,91 @@
 use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::fs;
 use std::io::{self, Read, Write};
 
-type Result<T> = ::std::result::Result<T, Box<::std::error::Error>>;
+macro_rules! err {
+    ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
+}
+
+type Result<T> = ::std::result::Result<T, Box<Error>>;
 
 fn main() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+    let input = match env::args_os().nth(1) {
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        Some(path) => fs::read_to_string(path)?,
+    };
 
-    part1(&input)?;
-    part2(&input)?;
-    Ok(())
-}
+    let mut changes: Vec<i32> = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let change = line.parse().or_else(|err| {
+            err!("line {}: failed to parse {:?}: {}", i + 1, line, err)
+        })?;
+        changes.push(change);
+    }
 
-fn part1(input: &str) -> Result<()> {
-    let mut freq = 0;
-    for line in input.lines() {
-        let change: i32 = line.parse()?;
-        freq += change;
+    writeln!(io::stdout(), "{}", sum_frequencies(&changes))?;
+    match first_repeat(&changes) {
+        None => return err!("no frequency is ever reached twice"),
+        Some(freq) => writeln!(io::stdout(), "{}", freq)?,
     }
-    writeln!(io::stdout(), "{}", freq)?;
     Ok(())
 }
 
-fn part2(input: &str) -> Result<()> {
+/// Returns the frequency reached after applying every change once.
+fn sum_frequencies(changes: &[i32]) -> i32 {
+    changes.iter().sum()
+}
+
+/// Returns the first frequency reached twice, applying the changes over and
+/// over, or `None` if no frequency is ever reached twice.
+fn first_repeat(changes: &[i32]) -> Option<i32> {
+    if !repeats(changes) {
+        return None;
+    }
     let mut freq = 0;
     let mut seen = HashSet::new();
     seen.insert(0);
 
     loop {
-        for line in input.lines() {
-            let change: i32 = line.parse()?;
+        for &change in changes {
             freq += change;
             if seen.contains(&freq) {
-                writeln!(io::stdout(), "{}", freq)?;
-                return Ok(());
+                return Some(freq);
             }
             seen.insert(freq);
         }
     }
 }
+
+/// Returns true if applying the changes over and over ever reaches the same
+/// frequency twice.
+///
+/// Let `f_0, ..., f_{n-1}` be the frequencies reached during the first pass
+/// before its last change, starting with `f_0 = 0`, and let `net` be the sum
+/// of all changes. Pass `k` then reaches exactly `f_i + k * net` for each
+/// `i`. If `net` is zero, every pass repeats the first one. Otherwise, two of
+/// these frequencies can only ever be equal if `f_i` and `f_j` have the same
+/// remainder modulo `net`, and if they do, the smaller one eventually
+/// catches up to the larger one (or vice versa when `net` is negative).
+fn repeats(changes: &[i32]) -> bool {
+    if changes.is_empty() {
+        return false;
+    }
+    let net = sum_frequencies(changes);
+    if net == 0 {
+        return true;
+    }
+    let mut freq: i32 = 0;
+    let mut remainders = HashSet::new();
+    for &change in &changes[..changes.len() - 1] {
+        remainders.insert(freq.rem_euclid(net));
+        freq += change;
+    }
+    remainders.insert(freq.rem_euclid(net));
+    remainders.len() < changes.len()
+}
@@ -1,66 +1,188 @@
+use std::collections::HashMap;
+use std::env;
 use std::io::{self, Read, Write};
 
 type Result<T> = ::std::result::Result<T, Box<::std::error::Error>>;
 
 fn main() -> Result<()> {
+    // Whether to compare every pair of ids in part 2, instead of looking for
+    // duplicates with one letter blanked out.
+    let mut pairwise = false;
+    // The most letters two ids may differ by in part 2.
+    let mut max_diff = 1;
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--pairwise" {
+            pairwise = true;
+        } else if arg == "--max-diff" {
+            max_diff = match args.next().and_then(|v| v.into_string().ok()) {
+                None => return Err(From::from("--max-diff requires a number")),
+                Some(v) => v.parse().map_err(|err| {
+                    format!("invalid --max-diff {:?}: {}", v, err)
+                })?,
+            };
+        } else {
+            return Err(From::from(format!("unrecognized argument: {:?}", arg)));
+        }
+    }
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
-    part1(&input)?;
-    part2(&input)?;
+    let ids: Vec<&str> = input.lines().collect();
+    writeln!(io::stdout(), "{}", checksum(&ids))?;
+    // Blanking out a single letter finds ids that differ by at most one, so
+    // it can only stand in for the pairwise search when that's the limit.
+    let common = if pairwise || max_diff != 1 {
+        common_letters_pairwise(&ids, max_diff)
+    } else {
+        common_letters(&ids)
+    };
+    match common {
+        None => return Err(From::from("could not find two correct box ids")),
+        Some((id1, id2, common)) => {
+            writeln!(io::stdout(), "{} (from {} and {})", common, id1, id2)?
+        }
+    }
     Ok(())
 }
 
-fn part1(input: &str) -> Result<()> {
-    let mut frequencies = [0u8; 256];
+/// Returns the number of ids with some letter appearing exactly twice,
+/// multiplied by the number of ids with some letter appearing exactly three
+/// times.
+///
+/// Each id is counted on its own, so ids don't need to be the same length.
+/// An empty id has no repeated letters, so it counts toward neither.
+fn checksum(ids: &[&str]) -> u32 {
     let (mut twos, mut threes) = (0, 0);
-    for line in input.lines() {
-        if !line.is_ascii() {
-            return Err(From::from("part1 only supports ASCII"));
+    for id in ids {
+        let (two, three) = repeated_letters(id);
+        if two {
+            twos += 1;
         }
-
-        for f in frequencies.iter_mut() {
-            *f = 0;
+        if three {
+            threes += 1;
         }
-        for b in line.as_bytes().iter().map(|&b| b as usize) {
+    }
+    twos * threes
+}
+
+/// Returns whether any letter in the given id appears exactly twice, and
+/// whether any letter appears exactly three times.
+fn repeated_letters(id: &str) -> (bool, bool) {
+    if id.is_ascii() {
+        // Most ids are ASCII, where a fixed size table is much faster than
+        // hashing every letter.
+        let mut frequencies = [0u8; 128];
+        for b in id.as_bytes().iter().map(|&b| b as usize) {
             frequencies[b] = frequencies[b].saturating_add(1);
         }
-        if frequencies.iter().any(|&f| f == 2) {
-            twos += 1;
+        return (frequencies.contains(&2), frequencies.contains(&3));
+    }
+
+    let mut frequencies: HashMap<char, u32> = HashMap::new();
+    for c in id.chars() {
+        *frequencies.entry(c).or_insert(0) += 1;
+    }
+    let two = frequencies.values().any(|&f| f == 2);
+    let three = frequencies.values().any(|&f| f == 3);
+    (two, three)
+}
+
+/// Returns two ids that differ by at most one letter, along with the letters
+/// they have in common, or `None` if there are no such ids. When there are
+/// several such pairs, this returns the same one as `common_letters_pairwise`.
+///
+/// Two ids differ only at position `i` exactly when they are the same once
+/// the letter at `i` is removed from both. So for each position, we remove it
+/// from every id and look for duplicates. This takes time proportional to
+/// the number of ids times the square of their length, instead of the square
+/// of the number of ids.
+fn common_letters<'a>(ids: &[&'a str]) -> Option<(&'a str, &'a str, String)> {
+    // The indices of the first matching pair, in the order the pairwise
+    // search would try them. For each id, it's enough to remember the first
+    // id with the same key, since any later one would come after it.
+    let mut best: Option<(usize, usize)> = None;
+    let mut found = |i: usize, j: usize| match best {
+        Some(b) if b <= (i, j) => {}
+        _ => best = Some((i, j)),
+    };
+
+    // Identical ids differ by no letters at all, which is within one. Most
+    // are also found below, but not empty ones, which have no positions.
+    let mut first: HashMap<&str, usize> = HashMap::new();
+    for (j, &id) in ids.iter().enumerate() {
+        match first.get(id) {
+            Some(&i) => found(i, j),
+            None => {
+                first.insert(id, j);
+            }
         }
-        if frequencies.iter().any(|&f| f == 3) {
-            threes += 1;
+    }
+
+    let longest = ids.iter().map(|id| id.chars().count()).max().unwrap_or(0);
+    for pos in 0..longest {
+        let mut first: HashMap<String, usize> = HashMap::new();
+        for (j, &id) in ids.iter().enumerate() {
+            if id.chars().count() <= pos {
+                continue;
+            }
+            let blanked: String = id
+                .chars()
+                .enumerate()
+                .filter(|&(i, _)| i != pos)
+                .map(|(_, c)| c)
+                .collect();
+            match first.get(&blanked) {
+                Some(&i) => found(i, j),
+                None => {
+                    first.insert(blanked, j);
+                }
+            }
         }
     }
-    writeln!(io::stdout(), "{}", twos * threes)?;
-    Ok(())
+
+    let (i, j) = best?;
+    let common = common_correct_letters(ids[i], ids[j], 1)
+        .expect("ids with a common key differ by at most one letter");
+    Some((ids[i], ids[j], common))
 }
 
-fn part2(input: &str) -> Result<()> {
-    let ids: Vec<&str> = input.lines().collect();
+/// Like `common_letters`, but compares every pair of ids, and returns the
+/// first pair that differs by at most `max_diff` letters.
+fn common_letters_pairwise<'a>(
+    ids: &[&'a str],
+    max_diff: usize,
+) -> Option<(&'a str, &'a str, String)> {
     for i in 0..ids.len() {
         for j in i+1..ids.len() {
-            if let Some(common) = common_correct_letters(&ids[i], &ids[j]) {
-                writeln!(io::stdout(), "{}", common)?;
-                return Ok(());
+            let common = common_correct_letters(&ids[i], &ids[j], max_diff);
+            if let Some(common) = common {
+                return Some((ids[i], ids[j], common));
             }
         }
     }
-    Err(From::from("could not find two correct box ids"))
+    None
 }
 
-fn common_correct_letters(id1: &str, id2: &str) -> Option<String> {
-    if id1.len() != id2.len() {
+/// Returns the letters two ids have in common, if they have the same length
+/// and differ by at most `max_diff` letters. Identical ids differ by none.
+fn common_correct_letters(
+    id1: &str,
+    id2: &str,
+    max_diff: usize,
+) -> Option<String> {
+    if id1.chars().count() != id2.chars().count() {
         return None;
     }
 
-    let mut found_one_wrong = false;
+    let mut wrong = 0;
     for (c1, c2) in id1.chars().zip(id2.chars()) {
         if c1 != c2 {
-            if found_one_wrong {
+            if wrong == max_diff {
                 return None;
             }
-            found_one_wrong = true;
+            wrong += 1;
         }
     }
     Some(
@@ -0,0 +1,55 @@
+use std::collections::{HashMap, HashSet};
+
+/// A map from step to all of its required dependency steps. The set of
+/// required dependency sets may be empty.
+pub type RequiredFor = HashMap<Step, HashSet<Step>>;
+
+pub type Step = char;
+
+/// Return the order in which steps should be completed by a single worker.
+///
+/// This is a topological sort of the given dependency graph, where ties
+/// between steps that are ready at the same time are broken by choosing the
+/// alphabetically first step. Steps that can never be completed (because of
+/// a cycle in their dependencies) are omitted.
+pub fn step_order(required_for: &RequiredFor) -> Vec<Step> {
+    let mut taken: HashSet<Step> = HashSet::new();
+    let mut order: Vec<Step> = vec![];
+    let mut next: Vec<Step> = vec![];
+    loop {
+        find_next_steps(required_for, &taken, &taken, &mut next);
+        let next_step = match next.pop() {
+            None => break,
+            Some(next_step) => next_step,
+        };
+        taken.insert(next_step);
+        order.push(next_step);
+    }
+    order
+}
+
+/// Populate `next_stack` with next steps such that the steps are sorted in
+/// reverse lexicographically with no duplicates.
+///
+/// Steps in `taken` are never added to the stack.
+///
+/// Steps in `done` signify which steps have already been completed. Only steps
+/// with all dependencies completed will be put on to the stack.
+pub fn find_next_steps(
+    required_for: &RequiredFor,
+    taken: &HashSet<Step>,
+    done: &HashSet<Step>,
+    next_stack: &mut Vec<Step>,
+) {
+    for (&step, dependencies) in required_for {
+        if taken.contains(&step) {
+            continue;
+        }
+        if dependencies.iter().all(|s| done.contains(s)) {
+            next_stack.push(step);
+        }
+    }
+    next_stack.sort();
+    next_stack.dedup();
+    next_stack.reverse();
+}
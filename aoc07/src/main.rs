@@ -3,11 +3,13 @@ extern crate lazy_static;
 extern crate regex;
 
 use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
 use std::io::{self, Read, Write};
 use std::result;
 use std::str::FromStr;
 
+use aoc07::{find_next_steps, step_order, RequiredFor, Step};
 use regex::Regex;
 
 macro_rules! err {
@@ -16,13 +18,9 @@ macro_rules! err {
 
 type Result<T> = result::Result<T, Box<Error>>;
 
-/// A map from step to all of its required dependency steps. The set of
-/// required dependency sets may be empty.
-type RequiredFor = HashMap<Step, HashSet<Step>>;
-
-type Step = char;
-
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
@@ -37,39 +35,71 @@ fn main() -> Result<()> {
     let mut required_for: RequiredFor = HashMap::new();
     for dep in deps {
         required_for.entry(dep.step).or_default().insert(dep.required);
+        // Steps that are only ever required by other steps (i.e., they have
+        // no dependencies of their own) still need an entry, otherwise they
+        // would never be considered ready.
         required_for.entry(dep.required).or_default();
     }
 
     part1(&required_for)?;
-    part2(&required_for)?;
+    part2(&required_for, &args)?;
     Ok(())
 }
 
-fn part1(required_for: &RequiredFor) -> Result<()> {
-    let mut taken: HashSet<Step> = HashSet::new();
-    let mut order: Vec<Step> = vec![];
-    let mut next: Vec<Step> = vec![];
-    loop {
-        find_next_steps(&required_for, &taken, &taken, &mut next);
-        let next_step = match next.pop() {
-            None => break,
-            Some(next_step) => next_step,
-        };
-        taken.insert(next_step);
-        order.push(next_step);
+#[derive(Clone, Debug)]
+struct Args {
+    /// The number of workers available in part 2.
+    workers: usize,
+    /// The number of seconds added to every step's duration in part 2.
+    base_duration: u32,
+    /// Whether to print a per-second timeline of each worker in part 2.
+    timeline: bool,
+}
+
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args { workers: 5, base_duration: 60, timeline: false };
+        let mut it = env::args().skip(1);
+        while let Some(arg) = it.next() {
+            match &*arg {
+                "--timeline" => args.timeline = true,
+                "--workers" => {
+                    args.workers = match it.next() {
+                        None => return err!("--workers requires a number"),
+                        Some(n) => n.parse()?,
+                    };
+                }
+                "--base-duration" => {
+                    args.base_duration = match it.next() {
+                        None => {
+                            return err!("--base-duration requires a number");
+                        }
+                        Some(n) => n.parse()?,
+                    };
+                }
+                unk => return err!("unrecognized argument: {:?}", unk),
+            }
+        }
+        if args.workers == 0 {
+            return err!("--workers must be at least 1");
+        }
+        Ok(args)
     }
+}
 
-    let answer: String = order.iter().cloned().collect();
+fn part1(required_for: &RequiredFor) -> Result<()> {
+    let answer: String = step_order(required_for).into_iter().collect();
     writeln!(io::stdout(), "step order: {}", answer)?;
     Ok(())
 }
 
-fn part2(required_for: &RequiredFor) -> Result<()> {
-    let mut workers = Workers::new(5);
+fn part2(required_for: &RequiredFor, args: &Args) -> Result<()> {
+    let mut workers = Workers::new(args.workers, args.base_duration);
     let mut assigned: HashSet<Step> = HashSet::new();
     let mut done: HashSet<Step> = HashSet::new();
     let mut order: Vec<Step> = vec![];
     let mut next: Vec<Step> = vec![];
+    let mut timeline = Timeline::default();
 
     let mut seconds = 0;
     loop {
@@ -77,6 +107,7 @@ fn part2(required_for: &RequiredFor) -> Result<()> {
 
         find_next_steps(&required_for, &assigned, &done, &mut next);
         if next.is_empty() && workers.all_idle() {
+            timeline.record(seconds, &workers, &order);
             break;
         }
         for worker in workers.available() {
@@ -87,39 +118,62 @@ fn part2(required_for: &RequiredFor) -> Result<()> {
             assigned.insert(next_step);
             workers.work_on(worker, next_step);
         }
+        timeline.record(seconds, &workers, &order);
         seconds += 1;
     }
 
+    if args.timeline {
+        timeline.print(&mut io::stdout(), workers.status.len())?;
+    }
     let answer: String = order.iter().cloned().collect();
     writeln!(io::stdout(), "step order (part 2): {}", answer)?;
     writeln!(io::stdout(), "total seconds: {}", seconds)?;
     Ok(())
 }
 
-/// Populate `next_stack` with next steps such that the steps are sorted in
-/// reverse lexicographically with no duplicates.
-///
-/// Steps in `taken` are never added to the stack.
-///
-/// Steps in `done` signify which steps have already been completed. Only steps
-/// with all dependencies completed will be put on to the stack.
-fn find_next_steps(
-    required_for: &RequiredFor,
-    taken: &HashSet<Step>,
-    done: &HashSet<Step>,
-    next_stack: &mut Vec<Step>,
-) {
-    for (&step, dependencies) in required_for {
-        if taken.contains(&step) {
-            continue;
+/// A record of what each worker was doing during each second of the part 2
+/// simulation, along with the steps completed so far. This mirrors the table
+/// shown in the puzzle's worked example.
+#[derive(Debug, Default)]
+struct Timeline {
+    seconds: Vec<Second>,
+}
+
+#[derive(Debug)]
+struct Second {
+    second: u32,
+    status: Vec<Status>,
+    done: String,
+}
+
+impl Timeline {
+    fn record(&mut self, second: u32, workers: &Workers, order: &[Step]) {
+        self.seconds.push(Second {
+            second,
+            status: workers.status.clone(),
+            done: order.iter().cloned().collect(),
+        });
+    }
+
+    fn print<W: Write>(&self, mut wtr: W, workers: usize) -> Result<()> {
+        write!(wtr, "Second")?;
+        for worker in 0..workers {
+            write!(wtr, "   Worker {}", worker + 1)?;
         }
-        if dependencies.iter().all(|s| done.contains(s)) {
-            next_stack.push(step);
+        writeln!(wtr, "   Done")?;
+        for sec in &self.seconds {
+            write!(wtr, "{:>6}", sec.second)?;
+            for status in &sec.status {
+                let step = match *status {
+                    Status::Idle => '.',
+                    Status::Working { step, .. } => step,
+                };
+                write!(wtr, "   {:^8}", step)?;
+            }
+            writeln!(wtr, "   {}", sec.done)?;
         }
+        Ok(())
     }
-    next_stack.sort();
-    next_stack.dedup();
-    next_stack.reverse();
 }
 
 /// Workers manages the simulation of a fixed size worker pool. This tracks
@@ -128,6 +182,8 @@ fn find_next_steps(
 #[derive(Debug)]
 struct Workers {
     status: Vec<Status>,
+    /// The number of seconds added to the duration of every step.
+    base_duration: u32,
 }
 
 type WorkerID = usize;
@@ -139,8 +195,8 @@ enum Status {
 }
 
 impl Workers {
-    fn new(count: usize) -> Workers {
-        Workers { status: vec![Status::Idle; count] }
+    fn new(count: usize, base_duration: u32) -> Workers {
+        Workers { status: vec![Status::Idle; count], base_duration }
     }
 
     fn available(&self) -> Vec<WorkerID> {
@@ -161,13 +217,17 @@ impl Workers {
         let status = &mut self.status[worker];
         assert!(*status == Status::Idle, "worker {} is not available", worker);
 
-        let remaining = (step as u32) - b'A' as u32 + 1 + 60;
+        let remaining = (step as u32) - b'A' as u32 + 1 + self.base_duration;
         *status = Status::Working { step, remaining }
     }
 
     /// Run one step in the simulation. Workers that have finished their work
     /// are transitioned to idle status.
+    ///
+    /// Steps that finish during the same second are appended to `order` in
+    /// alphabetical order, regardless of which workers completed them.
     fn run_one_step(&mut self, order: &mut Vec<Step>, done: &mut HashSet<Step>) {
+        let mut finished = vec![];
         for worker in 0..self.status.len() {
             let mut is_done = false;
             match self.status[worker] {
@@ -176,7 +236,7 @@ impl Workers {
                     *remaining -= 1;
                     if *remaining == 0 {
                         is_done = true;
-                        order.push(step);
+                        finished.push(step);
                         done.insert(step);
                     }
                 }
@@ -185,6 +245,8 @@ impl Workers {
                 self.status[worker] = Status::Idle;
             }
         }
+        finished.sort();
+        order.extend(finished);
     }
 }
 
@@ -208,9 +270,13 @@ impl FromStr for Dependency {
             None => return err!("unrecognized dependency"),
             Some(caps) => caps,
         };
-        Ok(Dependency {
+        let dep = Dependency {
             step: caps[2].as_bytes()[0] as Step,
             required: caps[1].as_bytes()[0] as Step,
-        })
+        };
+        if dep.step == dep.required {
+            return err!("step {} cannot depend on itself", dep.step);
+        }
+        Ok(dep)
     }
 }
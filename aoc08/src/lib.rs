@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::mem;
+use std::result;
+
+macro_rules! err {
+    ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
+}
+
+pub type Result<T> = result::Result<T, Box<Error>>;
+
+/// A node in the license tree. Each node has zero or more child nodes and
+/// zero or more metadata entries.
+#[derive(Debug, Default)]
+pub struct Node {
+    metadata: Vec<i32>,
+    children: Vec<Node>,
+    // Total count of numbers in this node. For the root node, this corresponds
+    // to the total count of all numbers in the tree.
+    len: usize,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // The default drop would recurse into each child, which overflows
+        // the call stack on deeply nested trees just like recursive parsing
+        // would. So we move every descendant on to an explicit stack
+        // instead. Each one is then dropped with no children left.
+        let mut stack = mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+impl Node {
+    /// Parse a tree from its flat representation, where each node is a
+    /// header (child count and metadata count), followed by its children and
+    /// then its metadata entries.
+    pub fn from_flat(flat: &[i32]) -> Result<Node> {
+        // We use an explicit stack of partially built nodes instead of
+        // recursion, so that deeply nested trees can't overflow the call
+        // stack.
+        if flat.is_empty() {
+            return err!("no numbers in input");
+        }
+        let mut stack = vec![PartialNode::from_header(flat, 0)?];
+        let mut pos = 2;
+        loop {
+            // OK because we only ever pop the root node right before
+            // returning.
+            let top = stack.last_mut().unwrap();
+            if top.children_remaining > 0 {
+                top.children_remaining -= 1;
+                let child = PartialNode::from_header(flat, pos)?;
+                pos += 2;
+                stack.push(child);
+                continue;
+            }
+
+            for _ in 0..top.meta_count {
+                let meta = match flat.get(pos) {
+                    None => return err!(
+                        "expected meta data at position {}, but input has \
+                         only {} numbers",
+                        pos, flat.len(),
+                    ),
+                    Some(&i) => i,
+                };
+                top.node.metadata.push(meta);
+                top.node.len += 1;
+                pos += 1;
+            }
+            let node = stack.pop().unwrap().node;
+            match stack.last_mut() {
+                None if node.len < flat.len() => {
+                    return err!(
+                        "found {} numbers after the end of the tree \
+                         (tree has {} numbers, input has {})",
+                        flat.len() - node.len, node.len, flat.len(),
+                    );
+                }
+                None => return Ok(node),
+                Some(parent) => {
+                    parent.node.len += node.len;
+                    parent.node.children.push(node);
+                }
+            }
+        }
+    }
+
+    /// The metadata entries of this node.
+    pub fn metadata(&self) -> &[i32] {
+        &self.metadata
+    }
+
+    /// The children of this node, in the order in which they appear.
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// Return an iterator over every node in this tree, including this one,
+    /// in pre-order. That is, each node is yielded before its children, and
+    /// children are yielded in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        Iter { stack: vec![self] }
+    }
+
+    /// Return the sum of all metadata entries in this tree.
+    pub fn sum_all_metadata(&self) -> i32 {
+        self.sum_and_value().0
+    }
+
+    /// Return the value of this node.
+    ///
+    /// The value of a node without children is the sum of its metadata.
+    /// Otherwise, each metadata entry `i` refers to the `i`th child (starting
+    /// at `1`), and the value is the sum of the values of the referenced
+    /// children. Entries that don't refer to a child are skipped.
+    pub fn value(&self) -> i32 {
+        self.sum_and_value().1
+    }
+
+    /// Return the sum of all metadata in this tree (part 1) along with the
+    /// value of this node (part 2), computed in a single traversal.
+    pub fn sum_and_value(&self) -> (i32, i32) {
+        // This is a post-order traversal with an explicit stack. Each node is
+        // visited twice: once to push its children and once more, after all
+        // of its children have been visited, to combine their results.
+        let mut stack: Vec<(&Node, bool)> = vec![(self, false)];
+        let mut results: Vec<(i32, i32)> = vec![];
+        while let Some((node, children_done)) = stack.pop() {
+            if !children_done {
+                stack.push((node, true));
+                // Push in reverse so that children are visited in order, and
+                // thus their results end up on `results` in order.
+                for child in node.children.iter().rev() {
+                    stack.push((child, false));
+                }
+                continue;
+            }
+
+            let first_child = results.len() - node.children.len();
+            let children = results.split_off(first_child);
+            let meta_sum: i32 = node.metadata.iter().cloned().sum();
+            let sum = meta_sum + children.iter().map(|&(s, _)| s).sum::<i32>();
+            let value = if children.is_empty() {
+                meta_sum
+            } else {
+                let mut value = 0;
+                for &i in &node.metadata {
+                    // Metadata entries that don't refer to a child, including
+                    // zero and negative entries, are skipped.
+                    if i < 1 {
+                        continue;
+                    }
+                    if let Some(&(_, v)) = children.get(i as usize - 1) {
+                        value += v;
+                    }
+                }
+                value
+            };
+            results.push((sum, value));
+        }
+        // OK because the root's result is always the last one pushed.
+        results.pop().unwrap()
+    }
+}
+
+/// A pre-order iterator over the nodes in a tree.
+#[derive(Debug)]
+struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<&'a Node> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().rev());
+        Some(node)
+    }
+}
+
+/// A node whose header has been read, but whose children and metadata may not
+/// have been read yet.
+#[derive(Debug)]
+struct PartialNode {
+    node: Node,
+    children_remaining: i32,
+    meta_count: i32,
+}
+
+impl PartialNode {
+    /// Read the header of a node starting at `pos`.
+    fn from_header(flat: &[i32], pos: usize) -> Result<PartialNode> {
+        if flat.len() < pos + 2 {
+            return err!(
+                "expected node header at position {}, but input has only \
+                 {} numbers",
+                pos, flat.len(),
+            );
+        }
+        Ok(PartialNode {
+            node: Node { metadata: vec![], children: vec![], len: 2 },
+            children_remaining: flat[pos],
+            meta_count: flat[pos + 1],
+        })
+    }
+}
@@ -1,11 +1,14 @@
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
 use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
 use std::io::{self, Read, Write};
+use std::path::PathBuf;
 use std::result;
-
-use regex_syntax::ParserBuilder;
-use regex_syntax::hir::{self, Hir, HirKind};
+use std::str::FromStr;
 
 macro_rules! err {
     ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
@@ -13,73 +16,412 @@ macro_rules! err {
 
 type Result<T> = result::Result<T, Box<Error>>;
 
-fn main() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
-
-    let expr = ParserBuilder::new()
-        .nest_limit(1000)
-        .build()
-        .parse(input.trim())?;
+/// The room we start in.
+const ORIGIN: Coordinate = Coordinate { x: 0, y: 0 };
 
-    let mut dists = Distances::new();
-    let origin = Coordinate { x: 0, y: 0 };
-    dists.insert(origin, 0);
-    distances(&expr, &mut dists, origin)?;
+fn main() -> Result<()> {
+    let args = Args::parse()?;
+    let input = match args.input {
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        Some(ref path) => fs::read_to_string(path)?,
+    };
 
-    let largest = dists.values().max().unwrap();
+    let route = input.trim();
+    let facility = Facility::from_route(route)?;
+    if args.render {
+        writeln!(io::stdout(), "{}", facility.render())?;
+    }
+    let dists = room_distances(route)?;
+    let largest = *dists.values().max().unwrap();
     writeln!(io::stdout(), "largest number of doors: {}", largest)?;
+    // Every room that far away, in reading order.
+    let mut furthest: Vec<Coordinate> = dists
+        .iter()
+        .filter(|&(_, &d)| d == largest)
+        .map(|(&c, _)| c)
+        .collect();
+    furthest.sort_by_key(|c| (c.y, c.x));
+    let furthest: Vec<String> =
+        furthest.iter().map(|c| format!("{},{}", c.x, c.y)).collect();
+    writeln!(io::stdout(), "furthest rooms: {}", furthest.join(" "))?;
+    writeln!(io::stdout(), "rooms: {}", dists.len())?;
     let atleast = dists.values().filter(|&&d| d >= 1000).count();
     writeln!(io::stdout(), "pass through at least 1000 doors: {}", atleast)?;
+    if args.histogram {
+        let mut buckets = vec![0; largest / 100 + 1];
+        for &d in dists.values() {
+            buckets[d / 100] += 1;
+        }
+        for (i, count) in buckets.iter().enumerate() {
+            writeln!(
+                io::stdout(),
+                "{:>5}-{:<5} {}",
+                i * 100, i * 100 + 99, count,
+            )?;
+        }
+    }
+    if let Some(to) = args.doors_to {
+        let doors = match shortest_doors_to(route, to)? {
+            None => return err!("there is no room at {},{}", to.x, to.y),
+            Some(doors) => doors,
+        };
+        writeln!(io::stdout(), "doors to {},{}: {}", to.x, to.y, doors)?;
+    }
+    if let Some(to) = args.path_to {
+        let path = match facility.path_to(to) {
+            None => return err!("there is no room at {},{}", to.x, to.y),
+            Some(path) => path,
+        };
+        writeln!(io::stdout(), "path to {},{}: {}", to.x, to.y, path)?;
+    }
     Ok(())
 }
 
-type Distances = HashMap<Coordinate, usize>;
+#[derive(Clone, Debug)]
+struct Args {
+    /// A file containing the route. When absent, stdin is read.
+    input: Option<PathBuf>,
+    /// Whether to draw a map of the facility.
+    render: bool,
+    /// When set, print the fewest doors needed to reach this room.
+    doors_to: Option<Coordinate>,
+    /// When set, print the shortest path from the origin to this room.
+    path_to: Option<Coordinate>,
+    /// Whether to print how many rooms are at each distance from the
+    /// origin, in buckets of 100 doors.
+    histogram: bool,
+}
+
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args {
+            input: None,
+            render: false,
+            doors_to: None,
+            path_to: None,
+            histogram: false,
+        };
+        let mut it = env::args_os().skip(1);
+        while let Some(arg) = it.next() {
+            if arg == "--render" {
+                args.render = true;
+            } else if arg == "--histogram" {
+                args.histogram = true;
+            } else if arg == "--doors-to" {
+                let room = flag_value(&mut it, "--doors-to")?.parse()?;
+                args.doors_to = Some(room);
+            } else if arg == "--path-to" {
+                args.path_to = Some(flag_value(&mut it, "--path-to")?.parse()?);
+            } else if arg.to_string_lossy().starts_with("--") {
+                return err!("unrecognized flag: {:?}", arg);
+            } else if args.input.is_none() {
+                args.input = Some(PathBuf::from(arg));
+            } else {
+                return err!("unexpected argument: {:?}", arg);
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Returns the value following the given flag, which must be valid UTF-8.
+fn flag_value(
+    it: &mut impl Iterator<Item = OsString>,
+    flag: &str,
+) -> Result<String> {
+    match it.next().map(|value| value.into_string()) {
+        None => err!("{} requires a value", flag),
+        Some(Err(value)) => err!("invalid value for {}: {:?}", flag, value),
+        Some(Ok(value)) => Ok(value),
+    }
+}
+
+/// Parse the given route regex and return the fewest number of doors needed
+/// to reach each room in the facility from the origin.
+fn room_distances(route: &str) -> Result<Distances> {
+    Ok(Facility::from_route(route)?.distances())
+}
+
+/// Parse the given route regex and return the fewest number of doors needed
+/// to reach the given room from the origin, or `None` if there is no such
+/// room.
+fn shortest_doors_to(route: &str, target: Coordinate) -> Result<Option<usize>> {
+    Ok(Facility::from_route(route)?.shortest_doors_to(target))
+}
+
+/// A graph of the rooms in the facility, with an edge for each door.
+#[derive(Clone, Debug, Default)]
+struct Facility {
+    /// Each room mapped to the rooms adjacent to it.
+    doors: HashMap<Coordinate, HashSet<Coordinate>>,
+}
+
+impl Facility {
+    /// Build the facility described by the given route regex.
+    fn from_route(route: &str) -> Result<Facility> {
+        let expr = Expr::parse(route)?;
+        let mut facility = Facility::default();
+        let mut starts = Rooms::new();
+        starts.insert(ORIGIN);
+        walk(&expr, &mut facility, &starts)?;
+        Ok(facility)
+    }
+
+    /// Add a door between two adjacent rooms.
+    fn add_door(&mut self, a: Coordinate, b: Coordinate) {
+        self.doors.entry(a).or_default().insert(b);
+        self.doors.entry(b).or_default().insert(a);
+    }
+
+    /// Return the fewest number of doors needed to reach each room from the
+    /// origin.
+    fn distances(&self) -> Distances {
+        self.shortest_paths()
+            .into_iter()
+            .map(|(room, (dist, _))| (room, dist))
+            .collect()
+    }
+
+    /// Return the fewest number of doors needed to reach the given room from
+    /// the origin, or `None` if there is no such room.
+    fn shortest_doors_to(&self, room: Coordinate) -> Option<usize> {
+        self.shortest_paths().get(&room).map(|&(dist, _)| dist)
+    }
+
+    /// Return the directions of a shortest path from the origin to the given
+    /// room, or `None` if there is no such room.
+    fn path_to(&self, room: Coordinate) -> Option<String> {
+        let paths = self.shortest_paths();
+        let mut room = room;
+        let mut path = vec![];
+        while room != ORIGIN {
+            let prev = paths.get(&room)?.1;
+            path.push(prev.direction_to(room));
+            room = prev;
+        }
+        Some(path.into_iter().rev().collect())
+    }
+
+    /// Find a shortest path from the origin to every room with a breadth
+    /// first search. Each room is mapped to its distance from the origin, in
+    /// doors, and the room before it on its path. (The origin is its own
+    /// previous room.)
+    ///
+    /// Since routes can loop back on themselves, the first path to a room
+    /// found by `walk` isn't necessarily the shortest.
+    fn shortest_paths(&self) -> HashMap<Coordinate, (usize, Coordinate)> {
+        let mut paths = HashMap::new();
+        paths.insert(ORIGIN, (0, ORIGIN));
+        let mut queue = VecDeque::new();
+        queue.push_back(ORIGIN);
+        while let Some(c) = queue.pop_front() {
+            let dist = paths[&c].0;
+            for &nextc in self.doors.get(&c).into_iter().flatten() {
+                if let Entry::Vacant(e) = paths.entry(nextc) {
+                    e.insert((dist + 1, c));
+                    queue.push_back(nextc);
+                }
+            }
+        }
+        paths
+    }
 
-fn distances(
-    expr: &Hir,
-    dists: &mut Distances,
-    c: Coordinate,
-) -> Result<Coordinate> {
-    // N.B. Even though this works for my input, it is most certainly wrong in
-    // the general case.
-    //
-    // See: https://github.com/BurntSushi/advent-of-code/issues/15
-    match *expr.kind() {
-        | HirKind::Empty
-        | HirKind::Literal(hir::Literal::Byte(_))
-        | HirKind::Class(_)
-        | HirKind::Anchor(_)
-        | HirKind::WordBoundary(_)
-        | HirKind::Repetition(_) => Ok(c),
-        HirKind::Literal(hir::Literal::Unicode(ch)) => {
-            let nextc = c.mv(ch)?;
-            let mut dist = dists[&c] + 1;
-            if dists.contains_key(&nextc) {
-                dist = cmp::min(dist, dists[&nextc])
+    /// Draw a map of the facility in the same style as the puzzle
+    /// statement, where `X` is the origin, `.` is a room, `|` and `-` are
+    /// doors and `#` is a wall.
+    fn render(&self) -> String {
+        let (mut min, mut max) = (ORIGIN, ORIGIN);
+        for c in self.doors.keys() {
+            min.x = cmp::min(min.x, c.x);
+            min.y = cmp::min(min.y, c.y);
+            max.x = cmp::max(max.x, c.x);
+            max.y = cmp::max(max.y, c.y);
+        }
+        // Every room gets its own cell, with a cell between each pair of
+        // rooms for a wall or a door, plus a wall around the whole thing.
+        let width = ((max.x - min.x + 1) * 2 + 1) as usize;
+        let height = ((max.y - min.y + 1) * 2 + 1) as usize;
+        let mut grid = vec![vec!['#'; width]; height];
+        let cell = |c: Coordinate| -> (usize, usize) {
+            let x = (c.x - min.x) * 2 + 1;
+            let y = (c.y - min.y) * 2 + 1;
+            (x as usize, y as usize)
+        };
+        for (&c, adjacent) in &self.doors {
+            let (x, y) = cell(c);
+            grid[y][x] = '.';
+            for &a in adjacent {
+                // The door sits halfway between the two rooms.
+                let (ax, ay) = cell(a);
+                let door = if ay == y { '|' } else { '-' };
+                grid[(y + ay) / 2][(x + ax) / 2] = door;
             }
-            dists.insert(nextc, dist);
-            Ok(nextc)
         }
-        HirKind::Group(ref g) => {
-            distances(&g.hir, dists, c)
+        let (x, y) = cell(ORIGIN);
+        grid[y][x] = 'X';
+
+        let rows: Vec<String> =
+            grid.into_iter().map(|row| row.into_iter().collect()).collect();
+        rows.join("\n")
+    }
+}
+
+/// A set of rooms in the facility.
+type Rooms = HashSet<Coordinate>;
+
+/// The fewest number of doors needed to reach each room from the origin.
+type Distances = HashMap<Coordinate, usize>;
+
+/// Follow the given expression from each of the given starting rooms,
+/// recording every door passed through, and return the set of rooms where it
+/// can end.
+///
+/// Tracking every possible end room matters when an alternation's branches
+/// end in different rooms. For example, in `^(N|E)E$`, the final `E` is
+/// taken from both the room to the north and the room to the east.
+fn walk(
+    expr: &Expr,
+    facility: &mut Facility,
+    starts: &Rooms,
+) -> Result<Rooms> {
+    match *expr {
+        Expr::Move(ch) => {
+            let mut ends = Rooms::new();
+            for &c in starts {
+                let nextc = c.mv(ch)?;
+                facility.add_door(c, nextc);
+                ends.insert(nextc);
+            }
+            Ok(ends)
         }
-        HirKind::Concat(ref exprs) => {
-            let mut nextc = c;
+        Expr::Seq(ref exprs) => {
+            let mut ends = starts.clone();
             for e in exprs {
-                nextc = distances(e, dists, nextc)?;
+                ends = walk(e, facility, &ends)?;
             }
-            Ok(nextc)
+            Ok(ends)
         }
-        HirKind::Alternation(ref exprs) => {
+        Expr::Alt(ref exprs) => {
+            let mut ends = Rooms::new();
             for e in exprs {
-                distances(e, dists, c)?;
+                ends.extend(walk(e, facility, starts)?);
             }
-            Ok(c)
+            Ok(ends)
         }
     }
 }
 
+/// A route regex, describing every path through the facility.
+#[derive(Clone, Debug)]
+enum Expr {
+    /// A move through a single door in the given direction.
+    Move(char),
+    /// A sequence of routes, followed one after the other. An empty sequence
+    /// doesn't move at all.
+    Seq(Vec<Expr>),
+    /// A choice between two or more routes, each starting from the same room.
+    Alt(Vec<Expr>),
+}
+
+impl Expr {
+    /// Parse a route regex, which must start with `^` and end with `$`.
+    fn parse(s: &str) -> Result<Expr> {
+        let mut p = Parser { route: s.as_bytes(), pos: 0 };
+        p.expect(b'^')?;
+        let expr = p.parse_alt()?;
+        if p.peek() == Some(b')') {
+            return err!("unmatched ')' at position {}", p.pos);
+        }
+        p.expect(b'$')?;
+        if p.pos < p.route.len() {
+            return err!("unexpected data after '$' at position {}", p.pos);
+        }
+        Ok(expr)
+    }
+}
+
+/// A recursive descent parser for route regexes, with this grammar:
+///
+/// ```text
+/// alt = seq ('|' seq)*
+/// seq = ('N' | 'E' | 'S' | 'W' | '(' alt ')')*
+/// ```
+#[derive(Debug)]
+struct Parser<'a> {
+    route: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_alt(&mut self) -> Result<Expr> {
+        let mut branches = vec![self.parse_seq()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            branches.push(self.parse_seq()?);
+        }
+        if branches.len() == 1 {
+            return Ok(branches.pop().unwrap());
+        }
+        Ok(Expr::Alt(branches))
+    }
+
+    fn parse_seq(&mut self) -> Result<Expr> {
+        let mut exprs = vec![];
+        loop {
+            match self.peek() {
+                Some(b'N') | Some(b'E') | Some(b'S') | Some(b'W') => {
+                    exprs.push(Expr::Move(self.route[self.pos] as char));
+                    self.pos += 1;
+                }
+                Some(b'(') => {
+                    let open = self.pos;
+                    self.pos += 1;
+                    exprs.push(self.parse_alt()?);
+                    if self.peek() != Some(b')') {
+                        return err!("unclosed '(' at position {}", open);
+                    }
+                    self.pos += 1;
+                }
+                Some(b'|') | Some(b')') | Some(b'$') | None => {
+                    return Ok(Expr::Seq(exprs));
+                }
+                Some(b) => {
+                    return err!(
+                        "unexpected {:?} at position {}",
+                        b as char, self.pos,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Consume the given byte, or return an error if it isn't next.
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        match self.peek() {
+            Some(b) if b == byte => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(b) => err!(
+                "expected {:?} at position {}, but found {:?}",
+                byte as char, self.pos, b as char,
+            ),
+            None => err!(
+                "expected {:?} at position {}, but found end of input",
+                byte as char, self.pos,
+            ),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.route.get(self.pos).cloned()
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 struct Coordinate {
     x: i64,
@@ -96,4 +438,28 @@ impl Coordinate {
             _ => err!("unknown direction: {:?}", direction),
         }
     }
+
+    /// Return the direction to move in to reach the given adjacent room.
+    fn direction_to(self, other: Coordinate) -> char {
+        match (other.x - self.x, other.y - self.y) {
+            (0, -1) => 'N',
+            (0, 1) => 'S',
+            (-1, 0) => 'W',
+            (1, 0) => 'E',
+            _ => unreachable!("{:?} is not adjacent to {:?}", other, self),
+        }
+    }
+}
+
+impl FromStr for Coordinate {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Coordinate> {
+        let mut parts = s.splitn(2, ',');
+        let (x, y) = match (parts.next(), parts.next()) {
+            (Some(x), Some(y)) => (x.trim(), y.trim()),
+            _ => return err!("expected coordinate 'X,Y', got {:?}", s),
+        };
+        Ok(Coordinate { x: x.parse()?, y: y.parse()? })
+    }
 }
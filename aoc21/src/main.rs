@@ -1,6 +1,12 @@
+use std::cmp;
 use std::collections::HashSet;
+use std::env;
 use std::error::Error;
-use std::io::{self, Read, Write};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::result;
 use std::str::{self, FromStr};
 
@@ -13,85 +19,345 @@ macro_rules! err {
 
 type Result<T> = result::Result<T, Box<Error>>;
 
+/// The default maximum number of instructions to execute when checking
+/// candidate values of R0. Programs that don't halt within this budget are
+/// reported as not halting.
+const DEFAULT_BUDGET: u64 = 100_000_000_000;
+
+/// The maximum number of comparisons that may be traced with
+/// `--trace-compare`. Larger values are clamped to this, since each trace
+/// line is written to stderr and the comparison may execute many millions of
+/// times.
+const MAX_TRACE_COMPARE: u64 = 100_000;
+
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
     let prog: Program = input.parse()?;
 
-    part1(&prog)?;
-    part2(&prog)?;
+    if !args.checks.is_empty() {
+        return check(&prog, &args.checks, args.budget);
+    }
+    let cmp = prog.comparison()?;
+    part1(&prog, cmp)?;
+    part2(&prog, cmp, &args)?;
     Ok(())
 }
 
-fn part1(prog: &Program) -> Result<()> {
+fn part1(prog: &Program, cmp: Comparison) -> Result<()> {
+    // For my input, the comparison is instruction 28 (`eqrr 5 0 3`). Since
+    // it is the only instruction that mentions R0, the smallest value of R0
+    // that causes a halt must be whatever R5 is the first time instruction
+    // 28 is executed.
+    let first = VM::default().exec_with_hook(prog, |ip, regs| {
+        if ip == cmp.ip {
+            ControlFlow::Break(regs.get(cmp.reg))
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    let first = match first {
+        None => return err!("program halted before reaching {:?}", cmp),
+        Some(first) => first,
+    };
+
     let mut vm = VM::default();
-    // Number was found by looking at the value of R5 the first time
-    // instruction 28 was executed (`eqrr 5 0 3`). In particular, this is the
-    // only instruction that mentions R0, so the smallest value of R0 must be
-    // whatever R5 is the first time instruction 28 is executed.
-    vm.registers.set(Register::R0, 7224964);
+    vm.registers.set(Register::R0, first);
     writeln!(io::stdout(), "result in register 0: {}", vm.exec(prog)?)?;
     Ok(())
 }
 
-fn part2(prog: &Program) -> Result<()> {
-    let mut vm = VM::default();
+fn part2(prog: &Program, cmp: Comparison, args: &Args) -> Result<()> {
+    let values =
+        VM::default().compared_values(prog, cmp, args.trace_compare)?;
+    if let Some(ref path) = args.values_out {
+        write_values(path, cmp, &values)?;
+    }
     writeln!(
         io::stdout(),
         "value for R0 to cause hault in most instructions: {}",
-        vm.exec_part2(prog)?,
+        // OK because compared_values never returns an empty sequence.
+        values.last().unwrap(),
     )?;
     Ok(())
 }
 
+/// Write the given compared values to the file at `path`, one per line,
+/// preceded by a comment describing the comparison they were collected from.
+fn write_values(path: &Path, cmp: Comparison, values: &[i64]) -> Result<()> {
+    let file = File::create(path).or_else(|err| {
+        err!("failed to create {}: {}", path.display(), err)
+    })?;
+    let mut wtr = BufWriter::new(file);
+    writeln!(wtr, "# comparison at ip {} against {:?}", cmp.ip, cmp.reg)?;
+    for value in values {
+        writeln!(wtr, "{}", value)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Run the program once for each of the given values of R0 and print a
+/// table reporting whether each one halted and how many instructions were
+/// executed before halting.
+///
+/// This is useful for confirming that the answer to part 1 halts after the
+/// fewest instructions and that the answer to part 2 halts after the most.
+fn check(prog: &Program, values: &[i64], budget: u64) -> Result<()> {
+    let mut out = io::stdout();
+    writeln!(out, "{:>12} {:>7} {:>15}", "R0", "halted", "instructions")?;
+    for &value in values {
+        let mut vm = VM::default();
+        vm.registers.set(Register::R0, value);
+        let run = vm.exec_budget(prog, budget);
+        writeln!(
+            out,
+            "{:>12} {:>7} {:>15}",
+            value,
+            if run.halted { "yes" } else { "no" },
+            run.instructions,
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+struct Args {
+    checks: Vec<i64>,
+    budget: u64,
+    values_out: Option<PathBuf>,
+    trace_compare: u64,
+}
+
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args {
+            checks: vec![],
+            budget: DEFAULT_BUDGET,
+            values_out: None,
+            trace_compare: 0,
+        };
+        let mut it = env::args_os().skip(1);
+        while let Some(arg) = it.next() {
+            let arg = match arg.into_string() {
+                Err(arg) => return err!("invalid argument: {:?}", arg),
+                Ok(arg) => arg,
+            };
+            match &*arg {
+                "--check" => {
+                    let value = flag_value(&mut it, &arg)?;
+                    let r0 = match value.strip_prefix("R0=") {
+                        None => return err!(
+                            "expected R0=<value> for --check, got {:?}",
+                            value,
+                        ),
+                        Some(r0) => r0,
+                    };
+                    args.checks.push(r0.parse().or_else(|err| {
+                        err!("invalid R0 value {:?}: {}", value, err)
+                    })?);
+                }
+                "--budget" => {
+                    let budget = flag_value(&mut it, &arg)?;
+                    args.budget = budget.parse().or_else(|err| {
+                        err!("invalid budget {:?}: {}", budget, err)
+                    })?;
+                }
+                "--trace-compare" => {
+                    let count = flag_value(&mut it, &arg)?;
+                    let count: u64 = count.parse().or_else(|err| {
+                        err!("invalid trace count {:?}: {}", count, err)
+                    })?;
+                    args.trace_compare = cmp::min(count, MAX_TRACE_COMPARE);
+                }
+                "--values-out" => {
+                    args.values_out = match it.next() {
+                        None => return err!("--values-out requires a path"),
+                        Some(path) => Some(PathBuf::from(path)),
+                    };
+                }
+                unk => return err!("unrecognized argument: {:?}", unk),
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Returns the value following the given flag, which must be valid UTF-8.
+fn flag_value(
+    it: &mut impl Iterator<Item = OsString>,
+    flag: &str,
+) -> Result<String> {
+    match it.next().map(|value| value.into_string()) {
+        None => err!("{} requires a value", flag),
+        Some(Err(value)) => err!("invalid value for {}: {:?}", flag, value),
+        Some(Ok(value)) => Ok(value),
+    }
+}
+
+/// The outcome of executing a program under an instruction budget.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Run {
+    /// Whether the program halted before exhausting its budget.
+    halted: bool,
+    /// The total number of instructions executed.
+    instructions: u64,
+}
+
 #[derive(Clone, Debug, Default)]
 struct VM {
     registers: Registers,
     ip: usize,
+    instructions: u64,
 }
 
 impl VM {
+    /// Execute the given program until it halts or until `budget`
+    /// instructions have been executed, whichever comes first.
+    fn exec_budget(&mut self, prog: &Program, budget: u64) -> Run {
+        while let Some(op) = prog.ops.get(self.ip) {
+            if self.instructions >= budget {
+                return Run { halted: false, instructions: self.instructions };
+            }
+            self.step(prog, op);
+        }
+        Run { halted: true, instructions: self.instructions }
+    }
+
+    /// Execute a single instruction (which must be the instruction at the
+    /// current instruction pointer) and advance the instruction pointer.
+    fn step(&mut self, prog: &Program, op: &Op) {
+        self.registers.set(prog.ipreg, self.ip as i64);
+        op.exec(&mut self.registers);
+        self.ip = self.registers.get(prog.ipreg) as usize + 1;
+        self.instructions += 1;
+    }
+
     fn exec(&mut self, prog: &Program) -> Result<i64> {
         while let Some(op) = prog.ops.get(self.ip) {
-            self.registers.set(prog.ipreg, self.ip as i64);
-            op.exec(&mut self.registers);
-            self.ip = self.registers.get(prog.ipreg) as usize + 1;
+            self.step(prog, op);
         }
         Ok(self.registers.get(Register::R0))
     }
 
-    // Like exec, but we collect all values of R5 at instruction 28. Assuming
-    // there is a pattern, we collect all such values in the cycle in the order
-    // in which they are seen. The last value in that cycle should be our
-    // answer.
-    //
-    // If there is no pattern... Then ¯\_(ツ)_/¯
-    fn exec_part2(&mut self, prog: &Program) -> Result<i64> {
-        let mut cycle = vec![];
-        let mut seen = HashSet::new();
+    /// Execute the given program, calling `hook` with the current
+    /// instruction pointer and registers before each instruction is executed.
+    ///
+    /// If the hook breaks, then execution stops immediately and the break
+    /// value is returned. If the program halts, then `None` is returned.
+    fn exec_with_hook<B>(
+        &mut self,
+        prog: &Program,
+        mut hook: impl FnMut(usize, &Registers) -> ControlFlow<B>,
+    ) -> Option<B> {
         while let Some(op) = prog.ops.get(self.ip) {
+            // Bind the instruction pointer first, so that the hook sees the
+            // registers exactly as the instruction will.
             self.registers.set(prog.ipreg, self.ip as i64);
-            op.exec(&mut self.registers);
-            self.ip = self.registers.get(prog.ipreg) as usize + 1;
-            if self.ip == 28 {
-                let r5 = self.registers.get(Register::R5);
-                if seen.contains(&r5) {
-                    return Ok(*cycle.last().unwrap());
+            if let ControlFlow::Break(value) = hook(self.ip, &self.registers) {
+                return Some(value);
+            }
+            self.step(prog, op);
+        }
+        None
+    }
+
+    /// Collect the values that R0 is compared against, in the order in which
+    /// they are seen, until the first repeat. Assuming there is a pattern,
+    /// the last value in this sequence is the value of R0 that causes the
+    /// program to halt after executing the most instructions.
+    ///
+    /// The registers at each of the first `trace` executions of the
+    /// comparison are written to stderr.
+    ///
+    /// If there is no pattern... Then ¯\_(ツ)_/¯
+    fn compared_values(
+        &mut self,
+        prog: &Program,
+        cmp: Comparison,
+        trace: u64,
+    ) -> Result<Vec<i64>> {
+        let stderr = io::stderr();
+        let mut trace_wtr = stderr.lock();
+        let mut hits: u64 = 0;
+        let mut values = vec![];
+        let mut seen = HashSet::new();
+        let found = self.exec_with_hook(prog, |ip, regs| {
+            if ip != cmp.ip {
+                return ControlFlow::Continue(());
+            }
+            let value = regs.get(cmp.reg);
+            let is_new = seen.insert(value);
+            hits += 1;
+            if hits <= trace {
+                let res = writeln!(
+                    trace_wtr,
+                    "compare #{}: registers {:?}, unique values: {}",
+                    hits, regs.0, seen.len(),
+                );
+                if let Err(err) = res {
+                    return ControlFlow::Break(Err(err));
                 }
-                seen.insert(r5);
-                cycle.push(r5);
             }
+            if !is_new {
+                return ControlFlow::Break(Ok(()));
+            }
+            values.push(value);
+            ControlFlow::Continue(())
+        });
+        match found {
+            None => err!("found no cycle"),
+            Some(Err(err)) => Err(Box::from(err)),
+            // There is always at least one value here, since the first
+            // comparison can never be a repeat.
+            Some(Ok(())) => Ok(values),
         }
-        err!("found no cycle")
     }
 }
 
+/// The instruction that compares R0 with another register. Since this is the
+/// only instruction that reads R0, the program halts exactly when R0 is equal
+/// to the value of `reg` at the time `ip` is executed.
+#[derive(Clone, Copy, Debug)]
+struct Comparison {
+    ip: usize,
+    reg: Register,
+}
+
 #[derive(Clone, Debug)]
 struct Program {
     ipreg: Register,
     ops: Vec<Op>,
 }
 
+impl Program {
+    /// Find the only instruction in this program that reads R0, which must be
+    /// an `eqrr` comparing R0 with some other register.
+    fn comparison(&self) -> Result<Comparison> {
+        let mut found = None;
+        for (ip, op) in self.ops.iter().enumerate() {
+            if !op.reads(Register::R0) {
+                continue;
+            }
+            let reg = match op.kind {
+                OpKind::Eqrr { a: Register::R0, b } => b,
+                OpKind::Eqrr { a, b: Register::R0 } => a,
+                _ => return err!("instruction {} reads R0 but isn't eqrr", ip),
+            };
+            if found.is_some() {
+                return err!("found multiple instructions that read R0");
+            }
+            found = Some(Comparison { ip, reg });
+        }
+        match found {
+            None => err!("found no instruction that reads R0"),
+            Some(cmp) => Ok(cmp),
+        }
+    }
+}
+
 impl FromStr for Program {
     type Err = Box<Error>;
 
@@ -162,6 +428,29 @@ impl Op {
         };
         regs.set(self.output, value);
     }
+
+    /// Returns true if and only if this instruction reads the given register.
+    fn reads(&self, r: Register) -> bool {
+        use self::OpKind::*;
+
+        match self.kind {
+            | Addr { a, b }
+            | Mulr { a, b }
+            | Banr { a, b }
+            | Borr { a, b }
+            | Gtrr { a, b }
+            | Eqrr { a, b } => a == r || b == r,
+            | Addi { a, .. }
+            | Muli { a, .. }
+            | Bani { a, .. }
+            | Bori { a, .. }
+            | Setr { a }
+            | Gtri { a, .. }
+            | Eqri { a, .. } => a == r,
+            Gtir { b, .. } | Eqir { b, .. } => b == r,
+            Seti { .. } => false,
+        }
+    }
 }
 
 type Immediate = i64;
@@ -169,7 +458,7 @@ type Immediate = i64;
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 struct Registers([i64; 6]);
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum Register {
     R0,
     R1,
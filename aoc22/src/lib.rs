@@ -0,0 +1,598 @@
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::error::Error;
+use std::result;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+macro_rules! err {
+    ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
+}
+
+pub type Result<T> = result::Result<T, Box<Error>>;
+
+/// The mouth of the cave, where every search starts.
+pub const MOUTH: Coordinate = Coordinate { x: 0, y: 0 };
+
+/// The strategy used to find the shortest time to the target.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Search {
+    /// Uniform cost search.
+    Dijkstra,
+    /// Uniform cost search guided by a lower bound on the remaining time.
+    AStar,
+}
+
+impl FromStr for Search {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Search> {
+        match s {
+            "dijkstra" => Ok(Search::Dijkstra),
+            "astar" => Ok(Search::AStar),
+            unk => err!("unknown search {:?} (want dijkstra or astar)", unk),
+        }
+    }
+}
+
+/// The rules governing how we may travel through the cave.
+#[derive(Clone, Debug)]
+pub struct RulesConfig {
+    /// The time it takes to move to an adjacent region.
+    pub move_cost: Time,
+    /// The time it takes to switch to a different tool.
+    pub switch_cost: Time,
+    /// Whether each tool may be used in each type of region, indexed by
+    /// region type and then by tool.
+    pub compatible: [[bool; 3]; 3],
+}
+
+impl Default for RulesConfig {
+    fn default() -> RulesConfig {
+        RulesConfig {
+            move_cost: 1,
+            switch_cost: 7,
+            compatible: [
+                // Torch, Gear,  Neither
+                [true,  true,  false], // Rocky
+                [false, true,  true],  // Wet
+                [true,  false, true],  // Narrow
+            ],
+        }
+    }
+}
+
+impl RulesConfig {
+    /// Returns true if the given tool may be used in the given region.
+    ///
+    /// By default, these are the rules from the puzzle statement:
+    ///
+    /// * In rocky regions, we can use the climbing gear or the torch, but
+    ///   not neither (we'd slip).
+    /// * In wet regions, we can use the climbing gear or neither, but not
+    ///   the torch (it'd get wet).
+    /// * In narrow regions, we can use the torch or neither, but not the
+    ///   climbing gear (it's too bulky).
+    ///
+    /// In other words, each region type rules out exactly one tool, and a
+    /// tool is ruled out by exactly one region type.
+    pub fn can_equip(&self, region: Region, equip: Equip) -> bool {
+        self.compatible[region.typ() as usize][equip as usize]
+    }
+}
+
+/// Statistics about a search for the shortest time to the target.
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    /// The total number of states pushed on to the priority queue.
+    pub pushed: usize,
+    /// The number of states popped off the priority queue and visited.
+    pub popped: usize,
+    /// The number of states popped off the priority queue but skipped,
+    /// because a faster route to them was found after they were pushed.
+    pub stale: usize,
+    /// The number of states never pushed, because they couldn't possibly
+    /// lead to the goal faster than a route already found.
+    pub pruned: usize,
+    /// The largest the priority queue ever got.
+    pub max_queue: usize,
+    /// The largest `x` and `y` coordinates of any state visited.
+    pub furthest: Coordinate,
+}
+
+/// Parse the depth and target coordinate from the puzzle input, which looks
+/// like this:
+///
+/// ```text
+/// depth: 510
+/// target: 10,10
+/// ```
+pub fn parse_scan(s: &str) -> Result<(usize, Coordinate)> {
+    let (mut depth, mut target) = (None, None);
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut parts = line.splitn(2, ':');
+        let (name, value) = match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) => (name.trim(), value.trim()),
+            _ => return err!("line {}: expected 'name: value'", i + 1),
+        };
+        match name {
+            "depth" => {
+                depth = Some(value.parse().or_else(|err| {
+                    err!("line {}: invalid depth {:?}: {}", i + 1, value, err)
+                })?);
+            }
+            "target" => {
+                target = Some(value.parse().or_else(|err| {
+                    err!("line {}: invalid target {:?}: {}", i + 1, value, err)
+                })?);
+            }
+            unk => return err!("line {}: unrecognized field {:?}", i + 1, unk),
+        }
+    }
+    match (depth, target) {
+        (Some(depth), Some(target)) => Ok((depth, target)),
+        (None, _) => err!("missing 'depth' in input"),
+        (_, None) => err!("missing 'target' in input"),
+    }
+}
+
+/// A cave whose regions are computed lazily, as they are needed.
+///
+/// In part 2, we might need to travel outside the rectangle created by the
+/// mouth and the target, and there's no obvious bound on how far. (A factor
+/// of 2 in both directions wasn't enough for my input!) Instead of guessing,
+/// we only compute the regions the search actually visits.
+#[derive(Clone, Debug)]
+pub struct Cave {
+    depth: usize,
+    target: Coordinate,
+    rules: RulesConfig,
+    /// The regions computed so far, indexed by `y` and then `x`. Rows are
+    /// extended on demand, but a row is never shorter than any row below it,
+    /// since each region depends on the regions above it and to its left.
+    regions: Vec<Vec<Region>>,
+}
+
+impl Cave {
+    /// Create a cave with the given depth and target, traveled according to
+    /// the given rules.
+    ///
+    /// Any depth is accepted, even one where the mouth doesn't permit the
+    /// torch. The risk level can still be computed for such a cave, but no
+    /// route can be found through it.
+    pub fn new(depth: usize, target: Coordinate, rules: RulesConfig) -> Cave {
+        Cave { depth, target, rules, regions: vec![] }
+    }
+
+    /// Return the total risk level of the rectangle from the mouth to the
+    /// target.
+    pub fn risk_level(&mut self) -> usize {
+        let target = self.target;
+        self.fill(target);
+        self.regions[..=target.y]
+            .par_iter()
+            .map(|row| row[..=target.x].iter().map(Region::risk_level))
+            .map(|row| row.sum::<usize>())
+            .sum()
+    }
+
+    /// Return the answers to both parts of the puzzle: the total risk level
+    /// and the time of the fastest route to the target, found with A*.
+    pub fn solve(&mut self) -> Result<Solution> {
+        let risk_level = self.risk_level();
+        let route =
+            self.shortest_route(Search::AStar, &mut SearchStats::default())?;
+        Ok(Solution { risk_level, time: route.time })
+    }
+
+    /// Find the fastest route from the mouth to the target.
+    pub fn shortest_route(
+        &mut self,
+        search: Search,
+        stats: &mut SearchStats,
+    ) -> Result<Route> {
+        let target = self.target;
+        self.shortest_route_to(target, search, stats)
+    }
+
+    /// Find the fastest route from the mouth to the given goal, which may be
+    /// any region in the cave. As with the target, we must be holding the
+    /// torch when we reach it.
+    ///
+    /// Regions computed by previous searches are reused.
+    pub fn shortest_route_to(
+        &mut self,
+        goal: Coordinate,
+        search: Search,
+        stats: &mut SearchStats,
+    ) -> Result<Route> {
+        // We always start at the mouth holding the torch, and must be holding
+        // it when we reach the goal. Since the cave is unbounded, a search for
+        // a state that can't exist would never terminate, so reject it up
+        // front.
+        if !self.can_equip(MOUTH, Equip::Torch) {
+            let depth = self.depth;
+            return err!(
+                "cannot start at the mouth holding the torch, \
+                 since depth {} makes it {:?}",
+                depth, self.region(MOUTH).typ(),
+            );
+        }
+        if !self.can_equip(goal, Equip::Torch) {
+            return err!(
+                "cannot reach {:?} holding the torch, since it is {:?}",
+                goal, self.region(goal).typ(),
+            );
+        }
+        let (move_cost, switch_cost) =
+            (self.rules.move_cost, self.rules.switch_cost);
+        // A lower bound on the time it takes to reach the goal from the
+        // given state: we need to make at least one move per step, and must
+        // switch to the torch if we aren't already holding it.
+        let heuristic = |c: Coordinate, equip: Equip| -> Time {
+            if search == Search::Dijkstra {
+                return 0;
+            }
+            let switch = if equip == Equip::Torch { 0 } else { switch_cost };
+            c.distance(goal) * move_cost + switch
+        };
+
+        let mut frontier = Frontier::new((goal, Equip::Torch), stats);
+        let h = heuristic(MOUTH, Equip::Torch);
+        frontier.push(None, 0, h, (MOUTH, Equip::Torch));
+        while let Some((time, c, equip)) = frontier.pop() {
+            if c == goal && equip == Equip::Torch {
+                let path = frontier.path((c, equip));
+                return Ok(Route { time, path });
+            }
+
+            // Try equipping different tools.
+            for &e in &[Equip::Torch, Equip::Gear, Equip::Neither] {
+                if e != equip && self.can_equip(c, e) {
+                    let (from, h) = (Some((c, equip)), heuristic(c, e));
+                    frontier.push(from, time + switch_cost, h, (c, e));
+                }
+            }
+            // Try visiting each neighbor.
+            for &(x, y) in &[(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                if (x < 0 && c.x == 0) || (y < 0 && c.y == 0) {
+                    continue;
+                }
+
+                let x = (c.x as i64 + x) as usize;
+                let y = (c.y as i64 + y) as usize;
+                let neighbor = Coordinate { x, y };
+                if self.can_equip(neighbor, equip) {
+                    let from = Some((c, equip));
+                    let h = heuristic(neighbor, equip);
+                    let state = (neighbor, equip);
+                    frontier.push(from, time + move_cost, h, state);
+                }
+            }
+        }
+        err!("could not find a path to {:?}", goal)
+    }
+
+    /// Render the rectangle from the mouth to the given corner (inclusive) in
+    /// the same format used by the puzzle statement. Each region on the given
+    /// path, other than the mouth and target, is drawn as `*`.
+    pub fn render(
+        &mut self,
+        corner: Coordinate,
+        path: &[Coordinate],
+    ) -> String {
+        let mut out = String::new();
+        for y in 0..=corner.y {
+            for x in 0..=corner.x {
+                let c = Coordinate { x, y };
+                out.push(if c == MOUTH {
+                    'M'
+                } else if c == self.target {
+                    'T'
+                } else if path.contains(&c) {
+                    '*'
+                } else {
+                    match self.region(c).typ() {
+                        RegionType::Rocky => '.',
+                        RegionType::Wet => '=',
+                        RegionType::Narrow => '|',
+                    }
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Returns true if the given tool may be used in the region at the given
+    /// coordinate.
+    pub fn can_equip(&mut self, c: Coordinate, equip: Equip) -> bool {
+        let region = self.region(c);
+        self.rules.can_equip(region, equip)
+    }
+
+    /// Compute every region in the rectangle from the mouth to the given
+    /// corner (inclusive).
+    ///
+    /// Unlike `region`, this works one anti-diagonal at a time instead of one
+    /// row at a time. Every region on an anti-diagonal depends only on the
+    /// regions on the previous one, so each anti-diagonal can be computed in
+    /// parallel. Gathering the anti-diagonals back into rows has a cost
+    /// though, so with only one thread, this falls back to `region`.
+    fn fill(&mut self, corner: Coordinate) {
+        if rayon::current_num_threads() == 1 {
+            self.region(corner);
+            return;
+        }
+        let (width, height) = (corner.x + 1, corner.y + 1);
+        // The anti-diagonals computed so far, where the `d`th anti-diagonal
+        // contains every region with `x + y == d`, ordered by `x`.
+        let mut diagonals: Vec<Vec<Region>> = vec![];
+        for d in 0..width + height - 1 {
+            // The range of `x` values on this anti-diagonal and the previous
+            // one.
+            let xmin = d.saturating_sub(height - 1);
+            let xend = cmp::min(d + 1, width);
+            let prev_xmin = d.saturating_sub(height);
+            let diagonal = {
+                let prev = diagonals.last().map(|p| &p[..]).unwrap_or(&[]);
+                (xmin..xend)
+                    .into_par_iter()
+                    .with_min_len(1024)
+                    .map(|x| {
+                        let y = d - x;
+                        let index = match self.fixed_geologic_index(x, y) {
+                            Some(index) => index,
+                            None => {
+                                let i = x - prev_xmin;
+                                let left = prev[i - 1].erosion_level as usize;
+                                let above = prev[i].erosion_level as usize;
+                                left * above
+                            }
+                        };
+                        Region::new(self.depth, index)
+                    })
+                    .collect()
+            };
+            diagonals.push(diagonal);
+        }
+
+        while self.regions.len() < height {
+            self.regions.push(vec![]);
+        }
+        for y in 0..height {
+            for x in self.regions[y].len()..width {
+                let d = x + y;
+                let xmin = d.saturating_sub(height - 1);
+                let region = diagonals[d][x - xmin];
+                self.regions[y].push(region);
+            }
+        }
+    }
+
+    /// Return the region at the given coordinate, computing it (and every
+    /// region above it and to its left) if necessary.
+    pub fn region(&mut self, c: Coordinate) -> Region {
+        if let Some(&region) = self.regions.get(c.y).and_then(|r| r.get(c.x)) {
+            return region;
+        }
+        while self.regions.len() <= c.y {
+            self.regions.push(vec![]);
+        }
+        for y in 0..=c.y {
+            for x in self.regions[y].len()..=c.x {
+                let index = self.geologic_index(x, y);
+                self.regions[y].push(Region::new(self.depth, index));
+            }
+        }
+        self.regions[c.y][c.x]
+    }
+
+    /// Compute the geologic index at the given coordinate. This assumes the
+    /// regions above and to the left have already been computed.
+    fn geologic_index(&self, x: usize, y: usize) -> usize {
+        if let Some(index) = self.fixed_geologic_index(x, y) {
+            return index;
+        }
+        let left = self.regions[y][x-1].erosion_level as usize;
+        let above = self.regions[y-1][x].erosion_level as usize;
+        left * above
+    }
+
+    /// Return the geologic index at the given coordinate if it doesn't depend
+    /// on any other region, which is the case for the mouth, the target and
+    /// every region on the edges of the cave.
+    fn fixed_geologic_index(&self, x: usize, y: usize) -> Option<usize> {
+        if (x == 0 && y == 0) || (x == self.target.x && y == self.target.y) {
+            Some(0)
+        } else if y == 0 {
+            Some(x * 16_807)
+        } else if x == 0 {
+            Some(y * 48_271)
+        } else {
+            None
+        }
+    }
+}
+
+pub type Time = usize; // minutes
+
+/// A position in the cave along with the tool equipped there.
+type State = (Coordinate, Equip);
+
+/// The answers to both parts of the puzzle.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Solution {
+    pub risk_level: usize,
+    pub time: Time,
+}
+
+/// The fastest route from the mouth to the target.
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub time: Time,
+    /// Every region visited along the way, starting at the mouth and ending
+    /// at the target.
+    pub path: Vec<Coordinate>,
+}
+
+/// The states waiting to be visited by a search, along with the fastest known
+/// time to every state seen so far and the state it was reached from.
+#[derive(Debug)]
+struct Frontier<'s> {
+    queue: BinaryHeap<Reverse<(Time, Time, Coordinate, Equip)>>,
+    best: HashMap<State, (Time, Option<State>)>,
+    goal: State,
+    stats: &'s mut SearchStats,
+}
+
+impl<'s> Frontier<'s> {
+    fn new(goal: State, stats: &'s mut SearchStats) -> Frontier<'s> {
+        let (queue, best) = (BinaryHeap::new(), HashMap::new());
+        Frontier { queue, best, goal, stats }
+    }
+
+    /// Add the given state to the frontier, reached from `from` at the given
+    /// time and needing at least `remaining` more time to reach the goal.
+    /// If the state has already been reached at least as quickly, or if it
+    /// can't beat the fastest known time to the goal, then this is a no-op.
+    fn push(
+        &mut self,
+        from: Option<State>,
+        time: Time,
+        remaining: Time,
+        (c, e): State,
+    ) {
+        if let Some(&(best, _)) = self.best.get(&(c, e)) {
+            if best <= time {
+                return;
+            }
+        }
+        if let Some(&(bound, _)) = self.best.get(&self.goal) {
+            if time + remaining > bound {
+                self.stats.pruned += 1;
+                return;
+            }
+        }
+        self.best.insert((c, e), (time, from));
+        self.queue.push(Reverse((time + remaining, time, c, e)));
+        self.stats.pushed += 1;
+        self.stats.max_queue =
+            cmp::max(self.stats.max_queue, self.queue.len());
+    }
+
+    /// Remove and return the state with the lowest priority.
+    fn pop(&mut self) -> Option<(Time, Coordinate, Equip)> {
+        while let Some(Reverse((_, time, c, e))) = self.queue.pop() {
+            // Skip stale entries, which were superseded by a faster route to
+            // the same state after they were pushed.
+            if time > self.best[&(c, e)].0 {
+                self.stats.stale += 1;
+                continue;
+            }
+            self.stats.popped += 1;
+            self.stats.furthest.x = cmp::max(self.stats.furthest.x, c.x);
+            self.stats.furthest.y = cmp::max(self.stats.furthest.y, c.y);
+            return Some((time, c, e));
+        }
+        None
+    }
+
+    /// Return the regions visited on the fastest known route to the given
+    /// state, starting from the mouth.
+    fn path(&self, mut state: State) -> Vec<Coordinate> {
+        let mut path = vec![state.0];
+        while let Some(from) = self.best[&state].1 {
+            // Switching tools doesn't move us to a new region.
+            if from.0 != state.0 {
+                path.push(from.0);
+            }
+            state = from;
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// A region of the cave. Only the erosion level is stored, since everything
+/// else about a region can be derived from it. This keeps memory usage low
+/// when the search explores far beyond the target.
+#[derive(Clone, Copy, Debug)]
+pub struct Region {
+    erosion_level: u16,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RegionType {
+    Rocky,
+    Wet,
+    Narrow,
+}
+
+impl Region {
+    fn new(cave_depth: usize, geologic_index: usize) -> Region {
+        // This always fits in a u16, since 20183 < 2^16.
+        let erosion_level = ((geologic_index + cave_depth) % 20183) as u16;
+        Region { erosion_level }
+    }
+
+    pub fn erosion_level(&self) -> usize {
+        self.erosion_level as usize
+    }
+
+    pub fn typ(&self) -> RegionType {
+        match self.erosion_level % 3 {
+            0 => RegionType::Rocky,
+            1 => RegionType::Wet,
+            2 => RegionType::Narrow,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn risk_level(&self) -> usize {
+        match self.typ() {
+            RegionType::Rocky => 0,
+            RegionType::Wet => 1,
+            RegionType::Narrow => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub enum Equip {
+    Torch,
+    Gear,
+    Neither,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct Coordinate {
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Coordinate {
+    pub fn distance(self, other: Coordinate) -> usize {
+        let dx = (self.x as i64 - other.x as i64).abs();
+        let dy = (self.y as i64 - other.y as i64).abs();
+        (dx + dy) as usize
+    }
+}
+
+impl FromStr for Coordinate {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Coordinate> {
+        let mut parts = s.splitn(2, ',');
+        let (x, y) = match (parts.next(), parts.next()) {
+            (Some(x), Some(y)) => (x.trim(), y.trim()),
+            _ => return err!("expected coordinate 'X,Y', got {:?}", s),
+        };
+        Ok(Coordinate { x: x.parse()?, y: y.parse()? })
+    }
+}
@@ -1,9 +1,18 @@
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap};
+use std::env;
 use std::error::Error;
-use std::io::{self, Write};
-use std::result;
-
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use aoc22::{
+    parse_scan, Cave, Coordinate, Equip, Result, RulesConfig, Search,
+    SearchStats,
+};
+
+// The depth and target from my puzzle input. These are only used when no
+// input is given.
 const DEPTH: usize = 9171;
 const TARGET: Coordinate = Coordinate { x: 7, y: 721 };
 
@@ -11,212 +20,146 @@ macro_rules! err {
     ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
 }
 
-type Result<T> = result::Result<T, Box<Error>>;
-
 fn main() -> Result<()> {
-    let cave = Cave::new(DEPTH, TARGET)?;
+    let args = Args::parse()?;
+    let input = match args.input {
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        Some(ref path) => fs::read_to_string(path)?,
+    };
+    let (depth, target) = if input.trim().is_empty() {
+        (DEPTH, TARGET)
+    } else {
+        parse_scan(&input)?
+    };
+
+    let mut cave = Cave::new(depth, target, args.rules);
+    let start = Instant::now();
     writeln!(io::stdout(), "risk level: {}", cave.risk_level())?;
-    writeln!(io::stdout(), "time to target: {}", cave.shortest_time()?)?;
-    Ok(())
-}
-
-#[derive(Clone, Debug)]
-struct Cave {
-    depth: usize,
-    target: Coordinate,
-    bound: Coordinate,
-    regions: Vec<Vec<Region>>,
-}
-
-impl Cave {
-    fn new(depth: usize, target: Coordinate) -> Result<Cave> {
-        let mut scanner = CaveScanner::new(depth, target);
-        scanner.scan();
-        scanner.cave()
+    if args.time {
+        writeln!(io::stdout(), "risk level took {:?}", start.elapsed())?;
     }
-
-    fn risk_level(&self) -> usize {
-        let mut risk_level = 0;
-        for y in 0..=self.target.y {
-            for x in 0..=self.target.x {
-                risk_level += self.regions[y][x].risk_level();
-            }
-        }
-        risk_level
+    let start = Instant::now();
+    let mut stats = SearchStats::default();
+    let route = cave.shortest_route(args.search, &mut stats)?;
+    writeln!(io::stdout(), "time to target: {}", route.time)?;
+    if args.time {
+        writeln!(io::stdout(), "search took {:?}", start.elapsed())?;
     }
-
-    fn shortest_time(&self) -> Result<usize> {
-        type Time = usize; // minutes
-        type PriorityQueue = BinaryHeap<Reverse<(Time, Coordinate, Equip)>>;
-
-        let mut queue: PriorityQueue = BinaryHeap::new();
-        let mut best: HashMap<(Coordinate, Equip), Time> = HashMap::new();
-
-        queue.push(Reverse((0, Coordinate { x: 0, y: 0 }, Equip::Torch)));
-        while let Some(Reverse((time, c, equip))) = queue.pop() {
-            if best.contains_key(&(c, equip)) && best[&(c, equip)] <= time {
-                continue;
-            }
-            best.insert((c, equip), time);
-            if c == self.target && equip == Equip::Torch {
-                return Ok(time);
-            }
-
-            // Try equipping different tools.
-            for &e in &[Equip::Torch, Equip::Gear, Equip::Neither] {
-                if self.regions[c.y][c.x].can_equip(e) {
-                    queue.push(Reverse((time + 7, c, e)));
-                }
-            }
-            // Try visiting each neighbor.
-            for &(x, y) in &[(0, -1), (1, 0), (0, 1), (-1, 0)] {
-                if (x < 0 && c.x == 0) || (y < 0 && c.y == 0) {
-                    continue;
-                }
-
-                let x = (c.x as i64 + x) as usize;
-                let y = (c.y as i64 + y) as usize;
-                if x > self.bound.x || y > self.bound.y {
-                    continue;
-                }
-                if self.regions[y][x].can_equip(equip) {
-                    let neighbor = Coordinate { x, y };
-                    queue.push(Reverse((time + 1, neighbor, equip)));
-                }
-            }
-        }
-        err!("could not find a path to {:?}", self.target)
+    if args.stats {
+        writeln!(
+            io::stdout(),
+            "states pushed: {}, popped: {}, stale: {}, pruned: {}",
+            stats.pushed, stats.popped, stats.stale, stats.pruned,
+        )?;
+        writeln!(
+            io::stdout(),
+            "max queue size: {}, furthest region: {},{}",
+            stats.max_queue, stats.furthest.x, stats.furthest.y,
+        )?;
     }
-}
-
-#[derive(Clone, Debug)]
-struct CaveScanner {
-    depth: usize,
-    target: Coordinate,
-    bound: Coordinate,
-    regions: Vec<Vec<Option<Region>>>,
-}
-
-impl CaveScanner {
-    fn new(depth: usize, target: Coordinate) -> CaveScanner {
-        // In part 2, we might need to travel outside the rectangle created
-        // by the mouth and the target. We heuristic expand the bounds by a
-        // factor of 2 in both directions. I don't think there is any guarantee
-        // that this works in general, but ¯\_(ツ)_/¯.
-        //
-        // Actually, a factor of 2 wasn't enough! It gave us an answer of 1009,
-        // which was too high. Bumping this up to a factor of 10 gave us the
-        // correct answer of 986. Oof.
-        let bound = Coordinate { x: target.x * 10, y: target.y * 10 };
-        let regions = vec![vec![None; bound.x + 1]; bound.y + 1];
-        CaveScanner { depth, target, bound, regions }
-    }
-
-    fn scan(&mut self) {
-        self.regions[0][0] = Some(Region::new(self.depth, 0));
-        self.regions[self.target.y][self.target.x] =
-            Some(Region::new(self.depth, 0));
-        for x in 0..=self.bound.x {
-            self.regions[0][x] = Some(Region::new(self.depth, x * 16_807));
-        }
-        for y in 0..=self.bound.y {
-            self.regions[y][0] = Some(Region::new(self.depth, y * 48_271));
-        }
-        for y in 1..=self.bound.y {
-            for x in 1..=self.bound.x {
-                if x == self.target.x && y == self.target.y {
-                    continue;
-                }
-
-                // These unwraps are OK because we are guaranteed to have
-                // computed the region for left and above in a prior iteration.
-                let left = self.regions[y][x-1].as_ref().unwrap();
-                let above = self.regions[y-1][x].as_ref().unwrap();
-                let geologic_index = left.erosion_level * above.erosion_level;
-                let region = Region::new(self.depth, geologic_index);
-                self.regions[y][x] = Some(region);
-            }
+    for &goal in &args.also_targets {
+        // One goal that doesn't permit the torch shouldn't hide the others.
+        if !cave.can_equip(goal, Equip::Torch) {
+            writeln!(
+                io::stdout(),
+                "time to {},{}: unreachable holding the torch, \
+                 since it is {:?}",
+                goal.x, goal.y, cave.region(goal).typ(),
+            )?;
+            continue;
         }
+        let route = cave.shortest_route_to(goal, args.search, &mut stats)?;
+        writeln!(
+            io::stdout(),
+            "time to {},{}: {}",
+            goal.x, goal.y, route.time,
+        )?;
     }
-
-    fn cave(&self) -> Result<Cave> {
-        let mut cave = Cave {
-            depth: self.depth,
-            target: self.target,
-            bound: self.bound,
-            regions: vec![],
-        };
-        for y in 0..=self.bound.y {
-            let mut row = vec![];
-            for x in 0..=self.bound.x {
-                let region = match self.regions[y][x].clone() {
-                    None => return err!("unknown region at ({}, {})", x, y),
-                    Some(region) => region,
-                };
-                row.push(region);
-            }
-            cave.regions.push(row);
-        }
-        Ok(cave)
+    if let Some(corner) = args.render {
+        let path = if args.route { &route.path[..] } else { &[] };
+        write!(io::stdout(), "{}", cave.render(corner, path))?;
     }
+    Ok(())
 }
 
 #[derive(Clone, Debug)]
-struct Region {
-    typ: RegionType,
-    geologic_index: usize,
-    erosion_level: usize,
-}
-
-#[derive(Clone, Copy, Debug)]
-enum RegionType {
-    Rocky,
-    Wet,
-    Narrow,
+struct Args {
+    /// A file containing the puzzle input. When absent, stdin is read.
+    input: Option<PathBuf>,
+    search: Search,
+    rules: RulesConfig,
+    /// Whether to print statistics about the search for the target.
+    stats: bool,
+    /// Additional coordinates to find the shortest time to, after the target.
+    also_targets: Vec<Coordinate>,
+    /// Whether to print how long each part took.
+    time: bool,
+    /// When set, print the map of the cave from the mouth to this corner.
+    render: Option<Coordinate>,
+    /// Whether to overlay the fastest route on the rendered map.
+    route: bool,
 }
 
-impl Region {
-    fn new(cave_depth: usize, geologic_index: usize) -> Region {
-        let erosion_level = (geologic_index + cave_depth) % 20183;
-        let typ = match erosion_level % 3 {
-            0 => RegionType::Rocky,
-            1 => RegionType::Wet,
-            2 => RegionType::Narrow,
-            _ => unreachable!(),
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args {
+            input: None,
+            search: Search::AStar,
+            rules: RulesConfig::default(),
+            stats: false,
+            also_targets: vec![],
+            time: false,
+            render: None,
+            route: false,
         };
-        Region { typ, geologic_index, erosion_level }
-    }
-
-    fn risk_level(&self) -> usize {
-        match self.typ {
-            RegionType::Rocky => 0,
-            RegionType::Wet => 1,
-            RegionType::Narrow => 2,
+        let mut it = env::args_os().skip(1);
+        while let Some(arg) = it.next() {
+            if arg == "--search" {
+                args.search = flag_value(&mut it, "--search")?.parse()?;
+            } else if arg == "--move-cost" {
+                args.rules.move_cost =
+                    flag_value(&mut it, "--move-cost")?.parse()?;
+            } else if arg == "--switch-cost" {
+                args.rules.switch_cost =
+                    flag_value(&mut it, "--switch-cost")?.parse()?;
+            } else if arg == "--stats" {
+                args.stats = true;
+            } else if arg == "--also-target" {
+                let goal = flag_value(&mut it, "--also-target")?.parse()?;
+                args.also_targets.push(goal);
+            } else if arg == "--time" {
+                args.time = true;
+            } else if arg == "--render" {
+                args.render = Some(flag_value(&mut it, "--render")?.parse()?);
+            } else if arg == "--route" {
+                args.route = true;
+            } else if arg.to_string_lossy().starts_with("--") {
+                return err!("unrecognized flag: {:?}", arg);
+            } else if args.input.is_none() {
+                args.input = Some(PathBuf::from(arg));
+            } else {
+                return err!("unexpected argument: {:?}", arg);
+            }
         }
-    }
-
-    fn can_equip(&self, equip: Equip) -> bool {
-        use self::RegionType::*;
-        use self::Equip::*;
-
-        match (self.typ, equip) {
-            (Rocky, Torch) | (Rocky, Gear) => true,
-            (Wet, Gear) | (Wet, Neither) => true,
-            (Narrow, Torch) | (Narrow, Neither) => true,
-            _ => false,
+        if args.route && args.render.is_none() {
+            return err!("--route requires --render");
         }
+        Ok(args)
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
-enum Equip {
-    Torch,
-    Gear,
-    Neither,
-}
-
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
-struct Coordinate {
-    x: usize,
-    y: usize,
+/// Returns the value following the given flag, which must be valid UTF-8.
+fn flag_value(
+    it: &mut impl Iterator<Item = OsString>,
+    flag: &str,
+) -> Result<String> {
+    match it.next().map(|value| value.into_string()) {
+        None => err!("{} requires a value", flag),
+        Some(Err(value)) => err!("invalid value for {}: {:?}", flag, value),
+        Some(Ok(value)) => Ok(value),
+    }
 }
@@ -1,13 +1,18 @@
 #![allow(warnings)]
 
-use std::collections::HashSet;
+use std::cmp::{self, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+use std::env;
 use std::error::Error;
+use std::ffi::OsString;
 use std::io::{self, Read, Write};
 use std::result;
 use std::str::{self, FromStr};
 
 use lazy_static::lazy_static;
-use rand::Rng;
+use rayon::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use regex::Regex;
 
@@ -18,70 +23,483 @@ macro_rules! err {
 type Result<T> = result::Result<T, Box<Error>>;
 
 fn main() -> Result<()> {
+    let args = Args::parse()?;
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
 
     let bots: Bots = input.parse()?;
 
+    // Several bots may share the largest radius, and they need not have the
+    // same number of bots in range. When that happens, report each of them
+    // and take the largest count as the answer.
     let largest = bots.largest_radius();
-    let in_range = bots.in_range_of_bot(&largest);
-    writeln!(io::stdout(), "nanobots in range: {}", in_range)?;
-
-    // The solution to part 2 is very dissatisfying. We use a cobbled
-    // together version of simulated annealing and combine it with a somewhat
-    // intelligent initial sample of points. Specifically, for each bot, we
-    // sample points along the edge of its sphere of influence. The thinking
-    // here is that optimal coordinate is probably close to the edge of at
-    // least one sphere.
-    //
-    // Running this program does not guarantee the correct answer each time,
-    // and it probably takes too long to run to completion anyway. I guessed
-    // a few numbers as the distance appeared to stabilize and eventually got
-    // it right.
-    //
-    // guessed: 111_851_609 (too low), 832 in range
-    // 111_789_973 also has 832 in range.
-    // 111_770_929 also has 832 in range.
-    // guessed: 118_995_681
-    // guessed: 121_493_970 (853 in range)
-    // guessed: 121_493_971 (correct)
-    let best = search(&bots);
-    writeln!(io::stdout(), "BEST: {:?}", best);
-    let dist = Coordinate::origin().distance(&best);
-    writeln!(io::stdout(), "shortest distance: {}", dist)?;
+    let counts: Vec<u64> =
+        largest.iter().map(|b| bots.in_range_of_bot(b)).collect();
+    let part1 = *counts.iter().max().unwrap();
+    if !args.json && counts.iter().any(|&count| count != part1) {
+        for (bot, count) in largest.iter().zip(&counts) {
+            writeln!(
+                io::stdout(),
+                "bot at {:?} with radius {}: {} in range",
+                bot.pos, bot.radius, count,
+            )?;
+        }
+        writeln!(
+            io::stdout(),
+            "note: {} bots share the largest radius but disagree on the \
+             number in range, using the largest",
+            largest.len(),
+        )?;
+    }
+
+    let result = match args.search {
+        Search::Exact => best_coordinate_exact(&bots, args.max_ties),
+        Search::Anneal => {
+            let show = args.progress;
+            search(&bots, &args.anneal, args.max_ties, &mut |event| {
+                if show {
+                    eprintln!(
+                        "origin ({}/{}): {:?} => {:?} (in range: {}, dist: {})",
+                        event.origins, event.total, event.origin, event.best,
+                        event.in_range, event.distance,
+                    );
+                }
+            })
+        }
+        Search::Sweep => sweep_bound(&bots),
+    };
+    if args.json {
+        writeln!(io::stdout(), "{}", result.to_json(part1))?;
+    } else {
+        writeln!(io::stdout(), "nanobots in range: {}", part1)?;
+        match result.coordinate {
+            None => writeln!(
+                io::stdout(),
+                "best coordinate: unknown (in range: at most {})",
+                result.in_range,
+            )?,
+            Some(c) => writeln!(
+                io::stdout(),
+                "best coordinate: {:?} (in range: {})",
+                c, result.in_range,
+            )?,
+        }
+        if result.ties.len() > 1 {
+            writeln!(
+                io::stdout(),
+                "tied coordinates: {}{}",
+                result.ties.len(),
+                if result.truncated { " (stopped at --max-ties)" } else { "" },
+            )?;
+            for c in result.ties.iter().take(3) {
+                writeln!(io::stdout(), "    {:?}", c)?;
+            }
+        }
+        writeln!(io::stdout(), "shortest distance: {}", result.distance)?;
+    }
+
+    // The sweep is cheap, so use it to sanity check the other strategies.
+    // Its distance is only an estimate, and its count of bots in range only
+    // an upper bound, so a search may disagree with it without being wrong.
+    // But no coordinate can be in range of more bots than the bound.
+    if result.strategy != Search::Sweep {
+        let sweep = sweep_bound(&bots);
+        if !args.json {
+            writeln!(
+                io::stdout(),
+                "sweep estimate: {} (in range: at most {})",
+                sweep.distance, sweep.in_range,
+            )?;
+        }
+        if result.in_range > sweep.in_range {
+            writeln!(
+                io::stderr(),
+                "WARNING: search found {} bots in range, but the sweep \
+                 bounds it at {}",
+                result.in_range, sweep.in_range,
+            )?;
+        }
+    }
     Ok(())
 }
 
-fn search(bots: &Bots) -> Coordinate {
-    const INIT_TEMPERATURE: f64 = 1_000.0;
-    const COOLING_FACTOR: f64 = 0.9999;
-    const ITERS: usize = 1_000;
+#[derive(Clone, Debug)]
+struct Args {
+    search: Search,
+    anneal: AnnealConfig,
+    /// Whether to print the progress of the annealing search to stderr.
+    progress: bool,
+    /// Whether to print the answers as a single JSON object.
+    json: bool,
+    /// The most coordinates tied for the answer to part 2 to report.
+    max_ties: usize,
+}
 
-    fn prob(iter: usize, in_range_old: u64, in_range_new: u64) -> f64 {
-        let temp = COOLING_FACTOR.powi(iter as i32) * INIT_TEMPERATURE;
-        ((in_range_new as f64 - in_range_old as f64) / temp).exp()
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args {
+            search: Search::Exact,
+            anneal: AnnealConfig::default(),
+            progress: false,
+            json: false,
+            max_ties: 10,
+        };
+        let mut it = env::args_os().skip(1);
+        while let Some(arg) = it.next() {
+            let anneal = &mut args.anneal;
+            if arg == "--search" {
+                args.search = flag_value(&mut it, "--search")?.parse()?;
+            } else if arg == "--progress" {
+                args.progress = true;
+            } else if arg == "--json" {
+                args.json = true;
+            } else if arg == "--max-ties" {
+                args.max_ties = flag_value(&mut it, "--max-ties")?.parse()?;
+            } else if arg == "--seed" {
+                anneal.seed = Some(flag_value(&mut it, "--seed")?.parse()?);
+            } else if arg == "--temperature" {
+                anneal.temperature =
+                    flag_value(&mut it, "--temperature")?.parse()?;
+            } else if arg == "--cooling" {
+                anneal.cooling = flag_value(&mut it, "--cooling")?.parse()?;
+            } else if arg == "--iters" {
+                anneal.iters = flag_value(&mut it, "--iters")?.parse()?;
+            } else if arg == "--samples" {
+                anneal.samples = flag_value(&mut it, "--samples")?.parse()?;
+            } else if arg == "--step" {
+                anneal.step = flag_value(&mut it, "--step")?.parse()?;
+            } else {
+                return err!("unrecognized argument: {:?}", arg);
+            }
+        }
+        if args.max_ties == 0 {
+            return err!("--max-ties must be at least 1");
+        }
+        Ok(args)
+    }
+}
+
+/// Returns the value following the given flag, which must be valid UTF-8.
+fn flag_value(
+    it: &mut impl Iterator<Item = OsString>,
+    flag: &str,
+) -> Result<String> {
+    match it.next().map(|value| value.into_string()) {
+        None => err!("{} requires a value", flag),
+        Some(Err(value)) => err!("invalid value for {}: {:?}", flag, value),
+        Some(Ok(value)) => Ok(value),
+    }
+}
+
+/// The strategy used to find the best coordinate in part 2.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Search {
+    /// An exact branch and bound search. See `best_coordinate_exact`.
+    Exact,
+    /// My original simulated annealing search. See `search`.
+    Anneal,
+    /// A sweep over distances from the origin, which only finds a distance.
+    /// See `sweep_bound`.
+    Sweep,
+}
+
+impl Search {
+    fn name(&self) -> &'static str {
+        match *self {
+            Search::Exact => "exact",
+            Search::Anneal => "anneal",
+            Search::Sweep => "sweep",
+        }
+    }
+}
+
+impl FromStr for Search {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Search> {
+        match s {
+            "exact" => Ok(Search::Exact),
+            "anneal" => Ok(Search::Anneal),
+            "sweep" => Ok(Search::Sweep),
+            unk => {
+                err!("unknown search {:?} (want exact, anneal or sweep)", unk)
+            }
+        }
+    }
+}
+
+/// The parameters of the simulated annealing search.
+#[derive(Clone, Debug)]
+struct AnnealConfig {
+    /// The seed for the random number generator. When absent, a random seed
+    /// is used, so each run is different.
+    seed: Option<u64>,
+    /// The initial temperature.
+    temperature: f64,
+    /// The factor the temperature is multiplied by after each iteration.
+    cooling: f64,
+    /// The number of iterations to run from each starting point.
+    iters: usize,
+    /// The number of starting points to sample from the surface of each
+    /// bot's range.
+    samples: usize,
+    /// The largest distance moved along each axis when picking a neighbor.
+    /// My input needed a large step, but 1 works well for the test input,
+    /// which has a considerably smaller grid.
+    step: i64,
+}
+
+impl Default for AnnealConfig {
+    fn default() -> AnnealConfig {
+        AnnealConfig {
+            seed: None,
+            temperature: 1_000.0,
+            cooling: 0.9999,
+            iters: 1_000,
+            samples: 10_000,
+            step: 10_000,
+        }
+    }
+}
+
+/// The answer to part 2 found by one of the search strategies.
+#[derive(Clone, Debug)]
+struct SearchResult {
+    /// The best coordinate found. The sweep doesn't find one.
+    coordinate: Option<Coordinate>,
+    /// The number of bots in range of the best coordinate. For the sweep,
+    /// this is only an upper bound.
+    in_range: u64,
+    /// The distance from the origin to the best coordinate.
+    distance: i64,
+    /// The strategy that produced this result.
+    strategy: Search,
+    /// Every coordinate found that ties with the best one, in sorted order.
+    /// The best coordinate is the first.
+    ties: Vec<Coordinate>,
+    /// Whether the search stopped looking for ties because it hit the
+    /// limit on how many to report. If so, there may be more.
+    truncated: bool,
+}
+
+impl SearchResult {
+    fn new(bots: &Bots, c: Coordinate, strategy: Search) -> SearchResult {
+        SearchResult {
+            coordinate: Some(c),
+            in_range: bots.in_range(&c),
+            distance: Coordinate::origin().distance(&c),
+            strategy,
+            ties: vec![c],
+            truncated: false,
+        }
+    }
+
+    /// Serialize this result, along with the answer to part 1, as a JSON
+    /// object. A missing coordinate is written as `null`.
+    fn to_json(&self, part1: u64) -> String {
+        let coordinate = match self.coordinate {
+            None => "null".to_string(),
+            Some(c) => format!("[{},{},{}]", c.x, c.y, c.z),
+        };
+        format!(
+            "{{\
+                \"part1\":{},\
+                \"part2\":{{\
+                    \"coordinate\":{},\
+                    \"in_range\":{},\
+                    \"distance\":{},\
+                    \"strategy\":\"{}\"\
+                }}\
+            }}",
+            part1,
+            coordinate,
+            self.in_range,
+            self.distance,
+            self.strategy.name(),
+        )
     }
+}
+
+/// Find the coordinate in range of the most bots, breaking ties by choosing
+/// the one closest to the origin. Up to `max_ties` coordinates that are just
+/// as good are reported too.
+///
+/// This is a branch and bound search over cubes. We start with a cube
+/// containing every bot, and repeatedly split the most promising cube into
+/// its eight octants. The number of bots in range of any part of a cube is
+/// an upper bound on the number of bots in range of any single coordinate
+/// in it, and the distance from the origin to the nearest part of a cube is
+/// a lower bound on the distance to any coordinate in it. So if we always
+/// split the cube with the most bots in range, breaking ties by the smallest
+/// distance, then the first cube we pop containing a single coordinate must
+/// be the answer.
+fn best_coordinate_exact(bots: &Bots, max_ties: usize) -> SearchResult {
+    let origin = Coordinate::origin();
+    let mut queue = BinaryHeap::new();
+    let push = |queue: &mut BinaryHeap<_>, cube: Cube| {
+        let (in_range, dist) = (cube.in_range(bots), cube.distance(&origin));
+        queue.push((in_range, Reverse(dist), Reverse(cube.size), cube));
+    };
+    push(&mut queue, Cube::bounding(bots));
+    let mut result: Option<SearchResult> = None;
+    while let Some((in_range, Reverse(dist), _, cube)) = queue.pop() {
+        if let Some(ref mut result) = result {
+            // Once we've found the answer, any other coordinate just as good
+            // must be in a cube with the same priority, and those are popped
+            // before any worse cube.
+            if (in_range, dist) != (result.in_range, result.distance) {
+                break;
+            }
+            if result.ties.len() == max_ties {
+                result.truncated = true;
+                break;
+            }
+            if cube.size == 1 {
+                result.ties.push(cube.min);
+                continue;
+            }
+        } else if cube.size == 1 {
+            result = Some(SearchResult::new(bots, cube.min, Search::Exact));
+            continue;
+        }
+        for octant in cube.octants() {
+            push(&mut queue, octant);
+        }
+    }
+    // The octants of every cube cover it, so we always eventually pop a
+    // cube containing a single coordinate.
+    let mut result = result.expect("search ran out of cubes");
+    result.ties.sort();
+    result.coordinate = Some(result.ties[0]);
+    result
+}
+
+/// Estimate the answer to part 2 by only considering each bot's distance
+/// from the origin. This finds the smallest distance that is in range of the
+/// most bots, along with that number of bots, but no coordinate.
+///
+/// A bot at distance `d` from the origin with radius `r` can only be in
+/// range of coordinates whose distance from the origin is in `[d-r, d+r]`.
+/// So the number of these intervals overlapping any one distance is an
+/// upper bound on the number of bots in range of any coordinate at that
+/// distance. This ignores the direction of each bot though, so the distance
+/// it finds isn't always right. (For my input, it's off by 2.)
+fn sweep_bound(bots: &Bots) -> SearchResult {
+    // Each interval contributes +1 at its start and -1 just past its end.
+    // Sorting puts the -1 before the +1 at the same distance, so intervals
+    // that merely touch aren't counted as overlapping.
+    let mut events = vec![];
+    for bot in &bots.bots {
+        let d = Coordinate::origin().distance(&bot.pos);
+        events.push((cmp::max(0, d - bot.radius), 1));
+        events.push((d + bot.radius + 1, -1));
+    }
+    events.sort();
+
+    let (mut best_dist, mut best_count) = (0, 0);
+    let mut count = 0;
+    for (dist, delta) in events {
+        count += delta;
+        if count > best_count {
+            best_dist = dist;
+            best_count = count;
+        }
+    }
+    SearchResult {
+        coordinate: None,
+        in_range: best_count as u64,
+        distance: best_dist,
+        strategy: Search::Sweep,
+        ties: vec![],
+        truncated: false,
+    }
+}
 
-    let mut rng = rand::thread_rng();
+/// A report on the progress of the annealing search, sent after each chunk of
+/// 100 starting points.
+#[derive(Clone, Debug)]
+struct ProgressEvent {
+    /// The number of starting points processed so far.
+    origins: usize,
+    /// The total number of starting points.
+    total: usize,
+    /// The first starting point in the most recent chunk.
+    origin: Coordinate,
+    /// The best coordinate found so far.
+    best: Coordinate,
+    /// The number of bots in range of the best coordinate.
+    in_range: u64,
+    /// The distance from the origin to the best coordinate.
+    distance: i64,
+}
+
+/// The solution I originally used for part 2, which is very dissatisfying.
+/// We use a cobbled together version of simulated annealing and combine it
+/// with a somewhat intelligent initial sample of points. Specifically, for
+/// each bot, we sample points along the edge of its sphere of influence. The
+/// thinking here is that optimal coordinate is probably close to the edge of
+/// at least one sphere.
+///
+/// Running this does not guarantee the correct answer each time, and it
+/// probably takes too long to run to completion anyway. I guessed a few
+/// numbers as the distance appeared to stabilize and eventually got it
+/// right.
+///
+/// guessed: 111_851_609 (too low), 832 in range
+/// 111_789_973 also has 832 in range.
+/// 111_770_929 also has 832 in range.
+/// guessed: 118_995_681
+/// guessed: 121_493_970 (853 in range)
+/// guessed: 121_493_971 (correct)
+fn search(
+    bots: &Bots,
+    config: &AnnealConfig,
+    max_ties: usize,
+    progress: &mut dyn FnMut(ProgressEvent),
+) -> SearchResult {
+    let prob = |iter: usize, in_range_old: u64, in_range_new: u64| -> f64 {
+        let temp = config.cooling.powi(iter as i32) * config.temperature;
+        ((in_range_new as f64 - in_range_old as f64) / temp).exp()
+    };
+    // Ties between equally good coordinates are broken by the coordinate
+    // itself, so that the answer doesn't depend on the iteration order of
+    // `best`.
+    let closest = |best: &HashSet<Coordinate>| -> Coordinate {
+        best.iter()
+            .cloned()
+            .min_by_key(|c| (Coordinate::origin().distance(&c), *c))
+            .unwrap()
+    };
+
+    // Every random number is derived from this seed. Each starting point
+    // gets its own generator, seeded by its index, so that the result
+    // doesn't depend on how the starting points are spread across threads.
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
     let mut origins = vec![];
     for bot in bots.bots.iter() {
-        for _ in 0..10000 {
+        for _ in 0..config.samples {
             origins.push(bot.random_surface_coordinate(&mut rng));
         }
     }
     origins.shuffle(&mut rng);
-
-    let mut best_in_range = bots.in_range(&origins[0]);
-    let mut best: HashSet<Coordinate> = HashSet::new();
-    best.insert(origins[0]);
-
-    for (i, &o) in origins.iter().enumerate() {
-        let mut cur_pos = o;
-        let mut cur_in_range = bots.in_range(&cur_pos);
-
-        for i in 0..ITERS {
-            let new_pos = cur_pos.random_neighbor(&mut rng);
-            let new_in_range = bots.in_range(&new_pos);
+    let index = bots.index();
+
+    // Anneal from the starting point at the given index, and return the
+    // best coordinates seen along the way along with the number of bots in
+    // range of them.
+    let walk = |origin: usize| -> (u64, HashSet<Coordinate>) {
+        let seed = seed.wrapping_add(1 + origin as u64);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut cur_pos = origins[origin];
+        let mut cur_in_range = index.in_range(&cur_pos);
+        let mut best_in_range = cur_in_range;
+        let mut best: HashSet<Coordinate> = HashSet::new();
+        best.insert(cur_pos);
+
+        for i in 0..config.iters {
+            let new_pos = cur_pos.random_neighbor(&mut rng, config.step);
+            let new_in_range = index.in_range(&new_pos);
             let p = prob(i, cur_in_range, new_in_range);
             if p >= 1.0 || rng.gen_bool(p) {
                 cur_pos = new_pos;
@@ -95,24 +513,51 @@ fn search(bots: &Bots) -> Coordinate {
                 best_in_range = new_in_range;
             }
         }
-
-        // print out progress
-        if i % 100 == 0 {
-            let zzz = best.iter()
-                .cloned()
-                .min_by_key(|c| Coordinate::origin().distance(&c))
-                .unwrap();
-            println!(
-                "origin ({}/{}): {:?} => {:?} (in range: {}, dist: {})",
-                i, origins.len(), o, zzz, best_in_range,
-                Coordinate::origin().distance(&zzz),
-            );
+        (best_in_range, best)
+    };
+    // Combine the best coordinates from two sets of starting points.
+    let merge = |a: (u64, HashSet<Coordinate>), b: (u64, HashSet<_>)| {
+        match a.0.cmp(&b.0) {
+            cmp::Ordering::Less => b,
+            cmp::Ordering::Greater => a,
+            cmp::Ordering::Equal => {
+                let (in_range, mut best) = a;
+                best.extend(b.1);
+                (in_range, best)
+            }
         }
+    };
+
+    // We process the starting points in chunks, so that we can report
+    // progress in between.
+    let mut best = (0, HashSet::new());
+    for start in (0..origins.len()).step_by(100) {
+        let end = cmp::min(start + 100, origins.len());
+        let chunk = (start..end)
+            .into_par_iter()
+            .map(walk)
+            .reduce(|| (0, HashSet::new()), merge);
+        best = merge(best, chunk);
+
+        let best_coord = closest(&best.1);
+        progress(ProgressEvent {
+            origins: end,
+            total: origins.len(),
+            origin: origins[start],
+            best: best_coord,
+            in_range: best.0,
+            distance: Coordinate::origin().distance(&best_coord),
+        });
     }
-    best.iter()
-        .cloned()
-        .min_by_key(|c| Coordinate::origin().distance(&c))
-        .unwrap()
+    let mut result = SearchResult::new(bots, closest(&best.1), Search::Anneal);
+    result.ties = best.1
+        .into_iter()
+        .filter(|c| Coordinate::origin().distance(c) == result.distance)
+        .collect();
+    result.ties.sort();
+    result.truncated = result.ties.len() > max_ties;
+    result.ties.truncate(max_ties);
+    result
 }
 
 #[derive(Clone, Debug)]
@@ -121,11 +566,10 @@ struct Bots {
 }
 
 impl Bots {
-    fn largest_radius(&self) -> &Bot {
-        self.bots
-            .iter()
-            .max_by_key(|b| b.radius)
-            .unwrap()
+    /// Returns every bot with the largest radius, in input order.
+    fn largest_radius(&self) -> Vec<&Bot> {
+        let radius = self.bots.iter().map(|b| b.radius).max().unwrap();
+        self.bots.iter().filter(|b| b.radius == radius).collect()
     }
 
     fn in_range_of_bot(&self, bot: &Bot) -> u64 {
@@ -136,8 +580,14 @@ impl Bots {
         self.bots.iter().filter(|b| b.in_range(c)).count() as u64
     }
 
+    /// Build a spatial index over these bots. Its `in_range` always agrees
+    /// with `Bots::in_range`, but is usually much faster.
+    fn index(&self) -> BotIndex {
+        BotIndex::new(self.bots.clone())
+    }
+
     fn total_dist(&self, c: &Coordinate) -> i64 {
-        self.bots.iter().map(|b| b.pos.distance(c) as i64).sum()
+        self.bots.iter().map(|b| b.pos.distance(c)).sum()
     }
 }
 
@@ -159,6 +609,147 @@ impl FromStr for Bots {
     }
 }
 
+/// A k-d tree over the positions of bots, for counting the bots in range of
+/// a coordinate without looking at every bot.
+///
+/// Each node covers a contiguous range of `bots`, and records the bounding
+/// box of their positions along with the smallest and largest radius among
+/// them. If the query coordinate is further from the box than the largest
+/// radius, then no bot in the node can be in range. If it is within the
+/// smallest radius of every corner of the box, then every bot is.
+#[derive(Clone, Debug)]
+struct BotIndex {
+    bots: Vec<Bot>,
+    nodes: Vec<IndexNode>,
+}
+
+#[derive(Clone, Debug)]
+struct IndexNode {
+    /// The range of `bots` covered by this node.
+    start: usize,
+    end: usize,
+    /// The bounding box of the positions of the bots in this node.
+    min: Coordinate,
+    max: Coordinate,
+    min_radius: i64,
+    max_radius: i64,
+    /// The indices of this node's children in `nodes`, if it isn't a leaf.
+    children: Option<(usize, usize)>,
+}
+
+impl BotIndex {
+    /// The number of bots at or below which a node is not split further.
+    const LEAF_SIZE: usize = 16;
+
+    fn new(mut bots: Vec<Bot>) -> BotIndex {
+        let mut nodes = vec![];
+        let len = bots.len();
+        BotIndex::build(&mut bots, 0, len, &mut nodes);
+        BotIndex { bots, nodes }
+    }
+
+    /// Add the node covering `bots[start..end]` and all of its descendants
+    /// to `nodes`, and return its index.
+    fn build(
+        bots: &mut [Bot],
+        start: usize,
+        end: usize,
+        nodes: &mut Vec<IndexNode>,
+    ) -> usize {
+        let slice = &mut bots[start..end];
+        let mut node = IndexNode {
+            start,
+            end,
+            min: slice[0].pos,
+            max: slice[0].pos,
+            min_radius: slice[0].radius,
+            max_radius: slice[0].radius,
+            children: None,
+        };
+        for bot in slice.iter() {
+            node.min.x = cmp::min(node.min.x, bot.pos.x);
+            node.min.y = cmp::min(node.min.y, bot.pos.y);
+            node.min.z = cmp::min(node.min.z, bot.pos.z);
+            node.max.x = cmp::max(node.max.x, bot.pos.x);
+            node.max.y = cmp::max(node.max.y, bot.pos.y);
+            node.max.z = cmp::max(node.max.z, bot.pos.z);
+            node.min_radius = cmp::min(node.min_radius, bot.radius);
+            node.max_radius = cmp::max(node.max_radius, bot.radius);
+        }
+        let id = nodes.len();
+        nodes.push(node.clone());
+        if slice.len() <= BotIndex::LEAF_SIZE {
+            return id;
+        }
+
+        // Split at the median along the widest axis of the bounding box.
+        let (dx, dy, dz) = (
+            node.max.x - node.min.x,
+            node.max.y - node.min.y,
+            node.max.z - node.min.z,
+        );
+        if dx >= dy && dx >= dz {
+            slice.sort_by_key(|b| b.pos.x);
+        } else if dy >= dz {
+            slice.sort_by_key(|b| b.pos.y);
+        } else {
+            slice.sort_by_key(|b| b.pos.z);
+        }
+        let mid = start + slice.len() / 2;
+        let left = BotIndex::build(bots, start, mid, nodes);
+        let right = BotIndex::build(bots, mid, end, nodes);
+        nodes[id].children = Some((left, right));
+        id
+    }
+
+    fn in_range(&self, c: &Coordinate) -> u64 {
+        self.in_range_node(0, c)
+    }
+
+    fn in_range_node(&self, id: usize, c: &Coordinate) -> u64 {
+        let node = &self.nodes[id];
+        if node.nearest(c) > node.max_radius {
+            return 0;
+        }
+        if node.furthest(c) <= node.min_radius {
+            return (node.end - node.start) as u64;
+        }
+        match node.children {
+            Some((left, right)) => {
+                self.in_range_node(left, c) + self.in_range_node(right, c)
+            }
+            None => {
+                let bots = &self.bots[node.start..node.end];
+                bots.iter().filter(|b| b.in_range(c)).count() as u64
+            }
+        }
+    }
+}
+
+impl IndexNode {
+    /// The distance from the given coordinate to the nearest point in this
+    /// node's bounding box.
+    fn nearest(&self, c: &Coordinate) -> i64 {
+        let axis = |v: i64, min: i64, max: i64| {
+            cmp::max(0, cmp::max(min - v, v - max))
+        };
+        axis(c.x, self.min.x, self.max.x)
+        + axis(c.y, self.min.y, self.max.y)
+        + axis(c.z, self.min.z, self.max.z)
+    }
+
+    /// The distance from the given coordinate to the furthest point in this
+    /// node's bounding box.
+    fn furthest(&self, c: &Coordinate) -> i64 {
+        let axis = |v: i64, min: i64, max: i64| {
+            cmp::max((v - min).abs(), (v - max).abs())
+        };
+        axis(c.x, self.min.x, self.max.x)
+        + axis(c.y, self.min.y, self.max.y)
+        + axis(c.z, self.min.z, self.max.z)
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Bot {
     pos: Coordinate,
@@ -184,9 +775,9 @@ impl Bot {
             let (x, y, z) = (x * normal, y * normal, z * normal);
             let radius = self.radius as f64;
             return Coordinate {
-                x: (x * radius) as i32,
-                y: (y * radius) as i32,
-                z: (z * radius) as i32,
+                x: (x * radius) as i64,
+                y: (y * radius) as i64,
+                z: (z * radius) as i64,
             };
         }
     }
@@ -218,11 +809,86 @@ impl FromStr for Bot {
     }
 }
 
+/// An axis aligned cube of coordinates.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+struct Cube {
+    /// The corner of the cube with the smallest coordinates.
+    min: Coordinate,
+    /// The length of each side of the cube, which is always a power of 2.
+    size: i64,
+}
+
+impl Cube {
+    /// Return the smallest cube, with sides whose length is a power of 2,
+    /// that contains the origin and the position of every bot.
+    ///
+    /// The best coordinate is always in this cube: moving a coordinate
+    /// towards the cube along any axis can't take it out of range of any bot
+    /// or further from the origin.
+    fn bounding(bots: &Bots) -> Cube {
+        let origin = Coordinate::origin();
+        let (mut min, mut max) = (origin, origin);
+        for bot in &bots.bots {
+            min.x = cmp::min(min.x, bot.pos.x);
+            min.y = cmp::min(min.y, bot.pos.y);
+            min.z = cmp::min(min.z, bot.pos.z);
+            max.x = cmp::max(max.x, bot.pos.x);
+            max.y = cmp::max(max.y, bot.pos.y);
+            max.z = cmp::max(max.z, bot.pos.z);
+        }
+        let extent =
+            cmp::max(max.x - min.x, cmp::max(max.y - min.y, max.z - min.z)) + 1;
+        let mut size = 1;
+        while size < extent {
+            size *= 2;
+        }
+        Cube { min, size }
+    }
+
+    /// Split this cube into eight cubes with sides half as long. This cube
+    /// must not contain a single coordinate.
+    fn octants(&self) -> Vec<Cube> {
+        let half = self.size / 2;
+        let mut octants = Vec::with_capacity(8);
+        for &dx in &[0, half] {
+            for &dy in &[0, half] {
+                for &dz in &[0, half] {
+                    let min = Coordinate {
+                        x: self.min.x + dx,
+                        y: self.min.y + dy,
+                        z: self.min.z + dz,
+                    };
+                    octants.push(Cube { min, size: half });
+                }
+            }
+        }
+        octants
+    }
+
+    /// Return the distance from the given coordinate to the nearest
+    /// coordinate in this cube.
+    fn distance(&self, c: &Coordinate) -> i64 {
+        let axis = |min: i64, v: i64| -> i64 {
+            let max = min + self.size - 1;
+            cmp::max(0, cmp::max(min - v, v - max))
+        };
+        axis(self.min.x, c.x) + axis(self.min.y, c.y) + axis(self.min.z, c.z)
+    }
+
+    /// Return the number of bots in range of any coordinate in this cube.
+    fn in_range(&self, bots: &Bots) -> u64 {
+        bots.bots
+            .iter()
+            .filter(|b| self.distance(&b.pos) <= b.radius)
+            .count() as u64
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 struct Coordinate {
-    x: i32,
-    y: i32,
-    z: i32,
+    x: i64,
+    y: i64,
+    z: i64,
 }
 
 impl Coordinate {
@@ -231,24 +897,19 @@ impl Coordinate {
     }
 
     fn distance(&self, other: &Coordinate) -> i64 {
-        (self.x as i64 - other.x as i64).abs()
-        + (self.y as i64 - other.y as i64).abs()
-        + (self.z as i64 - other.z as i64).abs()
+        (self.x - other.x).abs()
+        + (self.y - other.y).abs()
+        + (self.z - other.z).abs()
     }
 
     fn random<R: Rng>(mut rng: R) -> Coordinate {
         Coordinate { x: rng.gen(), y: rng.gen(), z: rng.gen() }
     }
 
-    fn random_neighbor<R: Rng>(&self, mut rng: R) -> Coordinate {
-        // The commented out lines are for the test input, which has a
-        // considerably smaller grid.
-        // let dx = rng.gen_range(-1, 2);
-        // let dy = rng.gen_range(-1, 2);
-        // let dz = rng.gen_range(-1, 2);
-        let dx = rng.gen_range(-10_000, 10_000);
-        let dy = rng.gen_range(-10_000, 10_000);
-        let dz = rng.gen_range(-10_000, 10_000);
+    fn random_neighbor<R: Rng>(&self, mut rng: R, step: i64) -> Coordinate {
+        let dx = rng.gen_range(-step, step + 1);
+        let dy = rng.gen_range(-step, step + 1);
+        let dz = rng.gen_range(-step, step + 1);
         Coordinate { x: self.x + dx, y: self.y + dy, z: self.z + dz }
     }
 }
@@ -1,81 +1,484 @@
-use std::cell::Cell;
+use std::env;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
+use std::fmt;
+use std::fs;
 use std::io::{self, Write};
+use std::path::PathBuf;
 use std::result;
+use std::str::FromStr;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+macro_rules! err {
+    ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
+}
 
 type Result<T> = result::Result<T, Box<Error>>;
 
 fn main() -> Result<()> {
-    let combat = Combat {
-        army1: Army::test1_immune(),
-        army2: Army::test1_infection(),
-    };
-    let winner = combat.fight_to_end();
-    writeln!(
-        io::stdout(),
-        "test: {} wins with {} units left",
-        winner.name,
-        winner.total_live_units(),
-    )?;
-
-    let combat = Combat {
-        army1: Army::real_immune(),
-        army2: Army::real_infection(),
+    let args = Args::parse()?;
+    let combat = match args.input {
+        Input::Path(ref path) => fs::read_to_string(path)?.parse()?,
+        Input::Builtin(ref name) => Combat::builtin(name)?,
     };
-    let winner = combat.fight_to_end();
-    writeln!(
-        io::stdout(),
-        "real: {} wins with {} units left",
-        winner.name,
-        winner.total_live_units(),
-    )?;
-
-    let mut combat = Combat {
-        army1: Army::test1_immune(),
-        army2: Army::test1_infection(),
-    };
-    combat.army1.boost(1570);
-    let winner = combat.fight_to_end();
-    writeln!(
-        io::stdout(),
-        "test: {} wins with {} units left after {} boost",
-        winner.name,
-        winner.total_live_units(),
-        1570,
-    )?;
-
-    // Trying this with boost values of 40 or 41 results in a combat that
-    // does not appear to terminate. I just kept trying higher values until
-    // I saw the first combat that terminated. 42 really is apparently the
-    // ultimate answer to the ultimate question of life, the Universe and
-    // Everything.
-    for boost in 42.. {
-        let mut combat = Combat {
-            army1: Army::real_immune(),
-            army2: Army::real_infection(),
-        };
-        combat.army1.boost(boost);
-        let winner = combat.fight_to_end();
-        if winner.name == "immune" {
+    combat.validate()?;
+
+    let (mut tracer, mut checker) = (Tracer::default(), Checker::default());
+    // The first error writing the trace. Once there is one, the fight goes on
+    // without tracing, and the error is reported when it ends.
+    let mut trace_err: Option<io::Error> = None;
+    let outcome = part1(&combat, &mut |event| {
+        if args.paranoid {
+            checker.event(&event);
+        }
+        if args.trace && trace_err.is_none() {
+            if let Err(err) = tracer.event(io::stdout(), event) {
+                trace_err = Some(err);
+            }
+        }
+    });
+    if let Some(err) = trace_err {
+        return Err(err.into());
+    }
+    writeln!(io::stdout(), "part 1: {}", outcome)?;
+    if args.stats {
+        let stats = &outcome.stats;
+        writeln!(io::stdout(), "    rounds: {}", stats.rounds)?;
+        for (i, army) in [&combat.army1, &combat.army2].iter().enumerate() {
             writeln!(
                 io::stdout(),
-                "real: {} wins with {} units left after {} boost",
-                winner.name,
-                winner.total_live_units(),
-                boost,
+                "    {}: dealt {} damage, lost {} units",
+                army.name, stats.damage[i], stats.lost[i],
             )?;
-            return Ok(());
-        } else if boost % 1 == 0 {
+        }
+    }
+    if args.verbose {
+        print_survivors(&outcome.survivors)?;
+    }
+
+    let outcome = part2(&combat)?;
+    writeln!(io::stdout(), "part 2: {}", outcome)?;
+    if args.verbose {
+        print_survivors(&outcome.fights[&outcome.boost].survivors)?;
+    }
+    if args.stats {
+        writeln!(
+            io::stdout(),
+            "    {:>6}  {:<9}  {:>6}  {:>9}",
+            "boost", "winner", "rounds", "survivors",
+        )?;
+        for (boost, fight) in &outcome.fights {
+            let winner = fight.winner.as_ref().map_or("stalemate", |w| w);
             writeln!(
                 io::stdout(),
-                "real: {} wins with {} units left after {} boost",
-                winner.name,
-                winner.total_live_units(),
-                boost,
+                "    {:>6}  {:<9}  {:>6}  {:>9}",
+                boost, winner, fight.stats.rounds, fight.units,
             )?;
         }
     }
-    Err(From::from("no minimal boost could be found"))
+    Ok(())
+}
+
+/// Print each surviving group in the same format as the puzzle statement.
+fn print_survivors(survivors: &[GroupSummary]) -> Result<()> {
+    for g in survivors {
+        writeln!(
+            io::stdout(),
+            "    {} group {} contains {} units",
+            army_title(&g.army), g.id, g.units,
+        )?;
+    }
+    Ok(())
+}
+
+/// Fight the given combat to the end, reporting what happens to `trace`.
+fn part1(combat: &Combat, trace: &mut dyn FnMut(Event)) -> FightOutcome {
+    let mut combat = combat.clone();
+    let mut stats = FightStats::default();
+    let result = combat.fight_to_end(&mut stats, trace);
+    FightOutcome::new(result, stats)
+}
+
+/// Find the smallest boost for which the immune system wins the given
+/// combat.
+///
+/// We double the boost until the immune system wins, and then binary search
+/// between the last two boosts, treating a stalemate as a loss. But winning
+/// isn't necessarily monotone in the boost. For example, a bigger boost can
+/// change which targets are chosen. So we also try each of the boosts in a
+/// small window below the one we found, and report if any of them win.
+fn part2(combat: &Combat) -> Result<BoostOutcome> {
+    // Every fight, by boost. Each boost is only fought once.
+    let mut fights: BTreeMap<u64, FightOutcome> = BTreeMap::new();
+    let mut immune_wins = |boost: u64| -> Option<u64> {
+        let fight = fights.entry(boost).or_insert_with(|| {
+            let mut combat = combat.clone();
+            combat.army1.boost(boost);
+            let mut stats = FightStats::default();
+            let result = combat.fight_to_end(&mut stats, &mut |_| {});
+            FightOutcome::new(result, stats)
+        });
+        match fight.winner {
+            Some(ref name) if name == "immune" => Some(fight.units),
+            _ => None,
+        }
+    };
+    if let Some(units) = immune_wins(0) {
+        return Ok(BoostOutcome {
+            boost: 0,
+            units,
+            non_monotonic: false,
+            fights,
+        });
+    }
+
+    // The immune system loses with `lo` and wins with `hi`.
+    let (mut lo, mut hi) = (0, 1);
+    while immune_wins(hi).is_none() {
+        if hi >= 1 << 40 {
+            return err!("no minimal boost could be found");
+        }
+        lo = hi;
+        hi *= 2;
+    }
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if immune_wins(mid).is_some() {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    let mut non_monotonic = false;
+    for boost in hi.saturating_sub(BOOST_WINDOW)..hi {
+        if immune_wins(boost).is_some() {
+            non_monotonic = true;
+            hi = boost;
+            break;
+        }
+    }
+    let units = immune_wins(hi).unwrap();
+    Ok(BoostOutcome { boost: hi, units, non_monotonic, fights })
+}
+
+/// The number of boosts just below the one found by binary search that are
+/// checked one at a time.
+const BOOST_WINDOW: u64 = 10;
+
+/// The end of a combat, without any borrows of the combat itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct FightOutcome {
+    /// The name of the winning army, or `None` for a stalemate.
+    winner: Option<String>,
+    /// The number of units the winning army has left, or zero for a
+    /// stalemate.
+    units: u64,
+    stats: FightStats,
+    /// Every group still alive at the end of the combat, in the order of
+    /// their armies and ids.
+    survivors: Vec<GroupSummary>,
+}
+
+impl FightOutcome {
+    fn new(result: FightResult, stats: FightStats) -> FightOutcome {
+        match result {
+            FightResult::Winner(army) => FightOutcome {
+                winner: Some(army.name.clone()),
+                units: army.total_live_units(),
+                stats,
+                survivors: army.survivors(),
+            },
+            FightResult::Stalemate(combat) => {
+                let mut survivors = combat.army1.survivors();
+                survivors.extend(combat.army2.survivors());
+                FightOutcome { winner: None, units: 0, stats, survivors }
+            }
+        }
+    }
+}
+
+/// A group that survived a combat.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct GroupSummary {
+    army: String,
+    id: u64,
+    units: u64,
+}
+
+/// Totals over every attack in a combat. The arrays are indexed by army,
+/// with 0 for `army1` and 1 for `army2`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct FightStats {
+    rounds: u64,
+    /// The damage dealt by each army, including damage that exceeded what
+    /// was needed to kill the units of the group attacked.
+    damage: [u64; 2],
+    /// The units lost by each army.
+    lost: [u64; 2],
+}
+
+impl FightStats {
+    /// Add the attacks from a single round.
+    fn add_round(&mut self, reports: &[AttackReport]) {
+        self.rounds += 1;
+        for r in reports {
+            self.damage[r.attacker.0] += r.damage;
+            self.lost[r.victim.0] += r.killed;
+        }
+    }
+}
+
+impl fmt::Display for FightOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.winner {
+            None => write!(f, "stalemate"),
+            Some(ref name) => {
+                write!(f, "{} wins with {} units left", name, self.units)
+            }
+        }
+    }
+}
+
+/// The smallest boost found for which the immune system wins.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct BoostOutcome {
+    boost: u64,
+    /// The number of immune system units left after winning.
+    units: u64,
+    /// Whether a boost below the one found by binary search also won.
+    non_monotonic: bool,
+    /// The outcome of the fight for every boost tried.
+    fights: BTreeMap<u64, FightOutcome>,
+}
+
+impl fmt::Display for BoostOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "immune wins with {} units left after {} boost",
+            self.units, self.boost,
+        )?;
+        if self.non_monotonic {
+            write!(f, " (a smaller boost won than binary search found)")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Args {
+    input: Input,
+    /// Whether to print a log of the part 1 combat in the same format as
+    /// the puzzle statement.
+    trace: bool,
+    /// Whether to print statistics about the part 1 combat, and a table of
+    /// every boost tried in part 2.
+    stats: bool,
+    /// Whether to print the groups left alive at the end of each part.
+    verbose: bool,
+    /// Whether to check the part 1 combat for internal consistency after
+    /// every round, and panic if something is off.
+    paranoid: bool,
+}
+
+/// Where the armies come from.
+#[derive(Clone, Debug)]
+enum Input {
+    /// A file in the puzzle's input format.
+    Path(PathBuf),
+    /// One of the armies built into this program. See `Combat::builtin`.
+    Builtin(String),
+}
+
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args {
+            input: Input::Builtin("real".to_string()),
+            trace: false,
+            stats: false,
+            verbose: false,
+            paranoid: false,
+        };
+        let mut it = env::args_os().skip(1);
+        while let Some(arg) = it.next() {
+            if arg == "--input" {
+                match it.next() {
+                    None => return err!("--input requires a value"),
+                    Some(path) => args.input = Input::Path(PathBuf::from(path)),
+                }
+            } else if arg == "--builtin" {
+                match it.next().map(|v| v.into_string()) {
+                    None => return err!("--builtin requires a value"),
+                    Some(Err(v)) => {
+                        return err!("invalid value for --builtin: {:?}", v);
+                    }
+                    Some(Ok(name)) => args.input = Input::Builtin(name),
+                }
+            } else if arg == "--trace" {
+                args.trace = true;
+            } else if arg == "--stats" {
+                args.stats = true;
+            } else if arg == "--verbose" {
+                args.verbose = true;
+            } else if arg == "--paranoid" {
+                args.paranoid = true;
+            } else {
+                return err!("unrecognized argument: {:?}", arg);
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Writes the events of a combat in the same format as the round log in
+/// the puzzle statement.
+#[derive(Clone, Debug, Default)]
+struct Tracer {
+    /// Whether the attack phase of the current round has started.
+    attacking: bool,
+}
+
+impl Tracer {
+    fn event<W: Write>(
+        &mut self,
+        mut wtr: W,
+        event: Event,
+    ) -> io::Result<()> {
+        match event {
+            Event::Units(combat) => {
+                if self.attacking {
+                    writeln!(wtr)?;
+                    self.attacking = false;
+                }
+                for army in &[&combat.army1, &combat.army2] {
+                    writeln!(wtr, "{}:", army_title(&army.name))?;
+                    let alive = army.alive_groups();
+                    if alive.is_empty() {
+                        writeln!(wtr, "No groups remain.")?;
+                    }
+                    for i in alive {
+                        let g = &army.groups[i];
+                        writeln!(
+                            wtr,
+                            "Group {} contains {} units",
+                            g.id, g.units,
+                        )?;
+                    }
+                }
+                writeln!(wtr)?;
+            }
+            Event::Estimate { attacker, victim, damage } => {
+                writeln!(
+                    wtr,
+                    "{} group {} would deal defending group {} {} damage",
+                    army_title(&attacker.army), attacker.id, victim.id, damage,
+                )?;
+            }
+            Event::Attack { attacker, victim, killed } => {
+                if !self.attacking {
+                    writeln!(wtr)?;
+                    self.attacking = true;
+                }
+                writeln!(
+                    wtr,
+                    "{} group {} attacks defending group {}, killing {} units",
+                    army_title(&attacker.army), attacker.id, victim.id, killed,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the events of a combat for things that should never happen:
+/// an army gaining units, a dead group attacking or being considered as a
+/// target, or kills that don't account for the units lost in a round.
+#[derive(Clone, Debug, Default)]
+struct Checker {
+    /// The names of the armies, in the same order as the other fields.
+    /// Empty until the first round starts.
+    names: Vec<String>,
+    /// The total units of each army at the start of the current round.
+    units: [u64; 2],
+    /// The units of each army killed so far in the current round.
+    killed: [u64; 2],
+}
+
+impl Checker {
+    fn event(&mut self, event: &Event) {
+        match *event {
+            Event::Units(combat) => {
+                let units = [
+                    combat.army1.total_live_units(),
+                    combat.army2.total_live_units(),
+                ];
+                if self.names.is_empty() {
+                    self.names = vec![
+                        combat.army1.name.clone(),
+                        combat.army2.name.clone(),
+                    ];
+                } else {
+                    for (i, name) in self.names.iter().enumerate() {
+                        assert!(
+                            units[i] <= self.units[i],
+                            "{} went from {} to {} units",
+                            name, self.units[i], units[i],
+                        );
+                        assert_eq!(
+                            self.units[i] - units[i], self.killed[i],
+                            "{} lost a different number of units than were \
+                             killed",
+                            name,
+                        );
+                    }
+                }
+                self.units = units;
+                self.killed = [0, 0];
+            }
+            Event::Estimate { attacker, victim, .. } => {
+                assert!(
+                    attacker.is_alive() && victim.is_alive(),
+                    "dead group in target selection: {} group {} and {} \
+                     group {}",
+                    attacker.army, attacker.id, victim.army, victim.id,
+                );
+            }
+            Event::Attack { attacker, victim, killed } => {
+                assert!(
+                    attacker.is_alive(),
+                    "dead {} group {} attacked", attacker.army, attacker.id,
+                );
+                // The victim may have just died, but it must have been alive
+                // before the attack.
+                assert!(
+                    victim.units + killed > 0,
+                    "dead {} group {} was attacked", victim.army, victim.id,
+                );
+                let i = self.army_index(&victim.army);
+                self.killed[i] += killed;
+            }
+        }
+    }
+
+    fn army_index(&self, name: &str) -> usize {
+        match self.names.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => panic!("unknown army {:?}", name),
+        }
+    }
+}
+
+/// Returns the name of an army as written in the puzzle statement.
+fn army_title(name: &str) -> &str {
+    match name {
+        "immune" => "Immune System",
+        "infection" => "Infection",
+        name => name,
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -84,10 +487,47 @@ struct Combat {
     army2: Army,
 }
 
+/// How a combat ended.
 #[derive(Clone, Debug)]
-struct Plan<'g> {
-    attacker: &'g Group,
-    victim: &'g Group,
+enum FightResult<'a> {
+    /// One army destroyed the other.
+    Winner(&'a Army),
+    /// Both armies are still alive, but a full round passed without any
+    /// units dying. Nothing will change in later rounds either.
+    Stalemate(&'a Combat),
+}
+
+/// Identifies a group in a combat by the index of its army (0 for `army1`
+/// and 1 for `army2`) and its index in that army's groups.
+type GroupId = (usize, usize);
+
+/// Something that happened during a combat.
+#[derive(Clone, Debug)]
+enum Event<'a> {
+    /// The state of both armies, reported at the start of each round and
+    /// once more when the combat ends.
+    Units(&'a Combat),
+    /// While selecting its target, the attacker worked out how much damage
+    /// it would deal to one of the enemy groups it could still choose.
+    Estimate { attacker: &'a Group, victim: &'a Group, damage: u64 },
+    /// The attacker attacked its target.
+    Attack { attacker: &'a Group, victim: &'a Group, killed: u64 },
+}
+
+/// The result of a single attack.
+#[derive(Clone, Debug)]
+struct AttackReport {
+    attacker: GroupId,
+    victim: GroupId,
+    damage: u64,
+    /// The number of units of the victim killed by the attack.
+    killed: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Plan {
+    attacker: GroupId,
+    victim: GroupId,
 }
 
 #[derive(Clone, Debug)]
@@ -100,7 +540,7 @@ struct Army {
 struct Group {
     army: String,
     id: u64,
-    units: Cell<u64>,
+    units: u64,
     unit_hp: u64,
     initiative: u64,
     attack: Attack,
@@ -114,34 +554,83 @@ struct Attack {
     damage: u64,
 }
 
+/// A type of damage, like `fire`. Any name is allowed, so that inputs with
+/// damage types other than the ones below still work.
 #[derive(Clone, Debug, Eq, PartialEq)]
-enum AttackKind {
-    Radiation,
-    Cold,
-    Fire,
-    Slashing,
-    Bludgeoning,
-}
+struct AttackKind(Cow<'static, str>);
+
+// The damage types in the puzzle statement and in my input.
+const RADIATION: AttackKind = AttackKind(Cow::Borrowed("radiation"));
+const COLD: AttackKind = AttackKind(Cow::Borrowed("cold"));
+const FIRE: AttackKind = AttackKind(Cow::Borrowed("fire"));
+const SLASHING: AttackKind = AttackKind(Cow::Borrowed("slashing"));
+const BLUDGEONING: AttackKind = AttackKind(Cow::Borrowed("bludgeoning"));
 
 impl Combat {
-    fn fight_to_end(&self) -> &Army {
+    /// Fight until the combat is over. The attacks are totaled in `stats`,
+    /// and everything that happens is reported to `trace`.
+    fn fight_to_end(
+        &mut self,
+        stats: &mut FightStats,
+        trace: &mut dyn FnMut(Event),
+    ) -> FightResult<'_> {
         loop {
-            if let Some(winner) = self.fight() {
-                return winner;
+            trace(Event::Units(self));
+            let reports = self.fight(trace);
+            stats.add_round(&reports);
+            if self.winner().is_some() {
+                break;
+            }
+            if reports.iter().all(|r| r.killed == 0) {
+                trace(Event::Units(self));
+                return FightResult::Stalemate(self);
             }
         }
+        trace(Event::Units(self));
+        FightResult::Winner(self.winner().unwrap())
     }
 
-    fn fight(&self) -> Option<&Army> {
-        for plan in self.target_selection() {
-            if !plan.attacker.is_alive() {
+    /// Fight a single round, and return a report for each attack made.
+    fn fight(&mut self, trace: &mut dyn FnMut(Event)) -> Vec<AttackReport> {
+        let mut reports = vec![];
+        for plan in self.target_selection(trace) {
+            // A group may have been wiped out earlier in this round, in
+            // which case it no longer gets to attack. Otherwise, its damage
+            // is computed from the units it has left right now, not from
+            // the units it had when it chose its target.
+            let attacker = self.group(plan.attacker);
+            if !attacker.is_alive() {
                 continue;
             }
 
-            let damage = plan.attacker.attack_damage(plan.victim);
-            plan.victim.absorb(damage);
+            let damage = attacker.attack_damage(self.group(plan.victim));
+            let killed = self.group_mut(plan.victim).absorb(damage);
+            reports.push(AttackReport {
+                attacker: plan.attacker,
+                victim: plan.victim,
+                damage,
+                killed,
+            });
+            trace(Event::Attack {
+                attacker: self.group(plan.attacker),
+                victim: self.group(plan.victim),
+                killed,
+            });
         }
-        self.winner()
+        reports
+    }
+
+    fn army(&self, index: usize) -> &Army {
+        if index == 0 { &self.army1 } else { &self.army2 }
+    }
+
+    fn group(&self, id: GroupId) -> &Group {
+        &self.army(id.0).groups[id.1]
+    }
+
+    fn group_mut(&mut self, id: GroupId) -> &mut Group {
+        let army = if id.0 == 0 { &mut self.army1 } else { &mut self.army2 };
+        &mut army.groups[id.1]
     }
 
     fn winner(&self) -> Option<&Army> {
@@ -155,40 +644,84 @@ impl Combat {
         }
     }
 
-    fn target_selection(&self) -> Vec<Plan> {
-        let mut plans = self.army1.target_selection(&self.army2);
-        plans.extend(self.army2.target_selection(&self.army1));
+    fn target_selection(&self, trace: &mut dyn FnMut(Event)) -> Vec<Plan> {
+        let mut plans = vec![];
+        // The order of the armies here doesn't matter, except that the
+        // puzzle's log shows the infection choosing its targets first.
+        for &(us, them) in &[(1, 0), (0, 1)] {
+            let choices =
+                self.army(us).target_selection(self.army(them), trace);
+            for (attacker, victim) in choices {
+                plans.push(Plan {
+                    attacker: (us, attacker),
+                    victim: (them, victim),
+                });
+            }
+        }
         plans.sort_by(|plan1, plan2| {
-            plan1.attacker.initiative.cmp(&plan2.attacker.initiative).reverse()
+            let init1 = self.group(plan1.attacker).initiative;
+            let init2 = self.group(plan2.attacker).initiative;
+            init1.cmp(&init2).reverse()
         });
         plans
     }
 }
 
+impl<'a> fmt::Display for FightResult<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FightResult::Winner(army) => write!(
+                f,
+                "{} wins with {} units left",
+                army.name,
+                army.total_live_units(),
+            ),
+            FightResult::Stalemate(_) => write!(f, "stalemate"),
+        }
+    }
+}
+
 impl Army {
     fn is_alive(&self) -> bool {
         self.groups.iter().any(|g| g.is_alive())
     }
 
     fn total_live_units(&self) -> u64 {
-        self.groups.iter().map(|g| g.units.get()).sum()
+        self.groups.iter().map(|g| g.units).sum()
     }
 
-    fn target_selection<'a>(&'a self, enemy: &'a Army) -> Vec<Plan<'a>> {
+    /// Returns pairs of indices of a group in this army and the group in the
+    /// enemy army that it will attack.
+    fn target_selection<'a>(
+        &'a self,
+        enemy: &'a Army,
+        trace: &mut dyn FnMut(Event<'a>),
+    ) -> Vec<(usize, usize)> {
         let mut plans = vec![];
-        let mut candidates: Vec<&Group> = enemy.alive_groups();
-        for g in self.target_selection_order() {
-            if let Some(i) = g.choose_victim(&candidates) {
-                plans.push(Plan { attacker: g, victim: candidates[i] });
-                candidates.swap_remove(i);
+        let mut candidates: Vec<usize> = enemy.alive_groups();
+        for i in self.target_selection_order() {
+            let g = &self.groups[i];
+            let mut victims: Vec<&Group> =
+                candidates.iter().map(|&c| &enemy.groups[c]).collect();
+            victims.sort_by_key(|v| v.id);
+            for victim in victims {
+                let damage = g.attack_damage(victim);
+                if damage > 0 {
+                    trace(Event::Estimate { attacker: g, victim, damage });
+                }
+            }
+            if let Some(c) = g.choose_victim(enemy, &candidates) {
+                plans.push((i, candidates[c]));
+                candidates.swap_remove(c);
             }
         }
         plans
     }
 
-    fn target_selection_order(&self) -> Vec<&Group> {
+    fn target_selection_order(&self) -> Vec<usize> {
         let mut groups = self.alive_groups();
-        groups.sort_by(|g1, g2| {
+        groups.sort_by(|&i1, &i2| {
+            let (g1, g2) = (&self.groups[i1], &self.groups[i2]);
             let power1 = g1.effective_power();
             let power2 = g2.effective_power();
             if power1 != power2 {
@@ -200,8 +733,20 @@ impl Army {
         groups
     }
 
-    fn alive_groups(&self) -> Vec<&Group> {
-        self.groups.iter().filter(|g| g.is_alive()).collect()
+    fn alive_groups(&self) -> Vec<usize> {
+        (0..self.groups.len()).filter(|&i| self.groups[i].is_alive()).collect()
+    }
+
+    fn survivors(&self) -> Vec<GroupSummary> {
+        self.groups
+            .iter()
+            .filter(|g| g.is_alive())
+            .map(|g| GroupSummary {
+                army: g.army.clone(),
+                id: g.id,
+                units: g.units,
+            })
+            .collect()
     }
 
     fn boost(&mut self, amount: u64) {
@@ -213,21 +758,29 @@ impl Army {
 
 impl Group {
     fn is_alive(&self) -> bool {
-        self.units.get() > 0
+        self.units > 0
     }
 
     fn effective_power(&self) -> u64 {
-        self.units.get() * self.attack.damage
+        self.units * self.attack.damage
     }
 
-    fn absorb(&self, damage: u64) -> u64 {
+    fn absorb(&mut self, damage: u64) -> u64 {
         let units_lost = damage / self.unit_hp;
-        let old = self.units.get();
-        self.units.set(old.saturating_sub(units_lost));
-        old - self.units.get()
+        let old = self.units;
+        self.units = old.saturating_sub(units_lost);
+        old - self.units
     }
 
-    fn choose_victim(&self, candidates: &[&Group]) -> Option<usize> {
+    /// Choose which of the given groups in the enemy army to attack, and
+    /// return its position in `candidates`.
+    fn choose_victim(
+        &self,
+        enemy: &Army,
+        candidates: &[usize],
+    ) -> Option<usize> {
+        let candidates: Vec<&Group> =
+            candidates.iter().map(|&i| &enemy.groups[i]).collect();
         let mut choice = None;
         for (i, &candidate) in candidates.iter().enumerate() {
             let damage = self.attack_damage(candidate);
@@ -258,7 +811,8 @@ impl Group {
 
             let init = candidate.initiative;
             let cur_init = candidates[cur].initiative;
-            assert!(init != cur_init);
+            // Combat::validate checks that initiatives are unique.
+            debug_assert!(init != cur_init);
             if init > cur_init {
                 choice = Some(i);
             }
@@ -292,283 +846,485 @@ impl Attack {
     }
 }
 
-// Below is just the construction of inputs. Didn't feel like writing a parser.
+impl Combat {
+    /// Returns one of the combats built into this program: `test` is the
+    /// example from the puzzle statement, `real` is my input and `stalemate`
+    /// is two groups that can't hurt each other.
+    fn builtin(name: &str) -> Result<Combat> {
+        let (army1, army2) = match name {
+            "test" => (Army::test1_immune(), Army::test1_infection()),
+            "real" => (Army::real_immune(), Army::real_infection()),
+            "stalemate" => {
+                (Army::stalemate_immune(), Army::stalemate_infection())
+            }
+            unk => {
+                return err!(
+                    "unknown builtin {:?} (want test, real or stalemate)",
+                    unk,
+                );
+            }
+        };
+        Ok(Combat { army1, army2 })
+    }
+}
+
+impl Combat {
+    /// Check the assumptions the rest of the program makes about the
+    /// armies: every group has a distinct initiative, and a non-zero number
+    /// of units, hit points and attack damage. Every violation is reported.
+    fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+        let mut initiatives: HashMap<u64, &Group> = HashMap::new();
+        for g in self.army1.groups.iter().chain(&self.army2.groups) {
+            let name = format!("{} group {}", army_title(&g.army), g.id);
+            if g.units == 0 {
+                problems.push(format!("{} has no units", name));
+            }
+            if g.unit_hp == 0 {
+                problems.push(format!("{} has no hit points", name));
+            }
+            if g.attack.damage == 0 {
+                problems.push(format!("{} has no attack damage", name));
+            }
+            if let Some(other) = initiatives.insert(g.initiative, g) {
+                problems.push(format!(
+                    "{} has the same initiative ({}) as {} group {}",
+                    name, g.initiative, army_title(&other.army), other.id,
+                ));
+            }
+        }
+        if !problems.is_empty() {
+            return err!("invalid armies: {}", problems.join("; "));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Combat {
+    type Err = Box<Error>;
+
+    /// Parse the armies in the puzzle's input format. Lines starting with
+    /// whitespace continue the previous line, as in the wrapped example.
+    fn from_str(s: &str) -> Result<Combat> {
+        let mut lines: Vec<String> = vec![];
+        for line in s.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match lines.last_mut() {
+                Some(last) if line.starts_with(char::is_whitespace) => {
+                    last.push(' ');
+                    last.push_str(line.trim());
+                }
+                _ => lines.push(line.trim().to_string()),
+            }
+        }
+
+        let (mut immune, mut infection) = (None, None);
+        let mut army: Option<&mut Option<Army>> = None;
+        for line in &lines {
+            if line.ends_with(':') {
+                let (slot, name) = match &line[..line.len() - 1] {
+                    "Immune System" => (&mut immune, "immune"),
+                    "Infection" => (&mut infection, "infection"),
+                    unk => return err!("unknown army {:?}", unk),
+                };
+                if slot.is_some() {
+                    return err!("found army {:?} more than once", line);
+                }
+                *slot = Some(Army { name: name.to_string(), groups: vec![] });
+                army = Some(slot);
+                continue;
+            }
+            let army = match army {
+                None => return err!("found group before any army: {:?}", line),
+                Some(ref mut army) => army.as_mut().unwrap(),
+            };
+            let id = army.groups.len() as u64 + 1;
+            let group = Group::parse(&army.name, id, line).or_else(|err| {
+                err!("failed to parse group {:?}: {}", line, err)
+            })?;
+            army.groups.push(group);
+        }
+        match (immune, infection) {
+            (Some(army1), Some(army2)) => Ok(Combat { army1, army2 }),
+            _ => err!("input must contain both an immune system and infection"),
+        }
+    }
+}
+
+impl Group {
+    fn parse(army: &str, id: u64, s: &str) -> Result<Group> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"(?x)
+                ^(?P<units>[0-9]+)\s+units\s+each\s+with\s+
+                (?P<hp>[0-9]+)\s+hit\s+points\s+
+                (?:\((?P<mods>[^)]*)\)\s+)?
+                with\s+an\s+attack\s+that\s+does\s+
+                (?P<damage>[0-9]+)\s+(?P<kind>[a-z]+)\s+damage\s+
+                at\s+initiative\s+(?P<initiative>[0-9]+)$
+            ").unwrap();
+        }
+
+        let caps = match RE.captures(s) {
+            None => return err!("unrecognized group"),
+            Some(caps) => caps,
+        };
+        let (mut weaknesses, mut immunities) = (vec![], vec![]);
+        if let Some(mods) = caps.name("mods") {
+            for part in mods.as_str().split(';') {
+                let mut pieces = part.trim().splitn(2, " to ");
+                let (list, kinds) = match (pieces.next(), pieces.next()) {
+                    (Some("weak"), Some(kinds)) => (&mut weaknesses, kinds),
+                    (Some("immune"), Some(kinds)) => (&mut immunities, kinds),
+                    _ => return err!("unrecognized weaknesses/immunities"),
+                };
+                for kind in kinds.split(',') {
+                    list.push(kind.trim().parse()?);
+                }
+            }
+        }
+        Ok(Group {
+            army: army.to_string(),
+            id,
+            units: caps["units"].parse()?,
+            unit_hp: caps["hp"].parse()?,
+            initiative: caps["initiative"].parse()?,
+            attack: Attack::new(
+                caps["kind"].parse()?,
+                caps["damage"].parse()?,
+            ),
+            weaknesses,
+            immunities,
+        })
+    }
+}
+
+impl FromStr for AttackKind {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<AttackKind> {
+        if s.is_empty() || !s.chars().all(|c| c.is_ascii_lowercase()) {
+            return err!("invalid attack type {:?}", s);
+        }
+        Ok(AttackKind(Cow::Owned(s.to_string())))
+    }
+}
+
+impl fmt::Display for AttackKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Below is just the construction of the builtin inputs, which I wrote before
+// there was a parser.
 
 impl Army {
     fn test1_immune() -> Army {
-        use self::AttackKind::*;
-
         Army {
             name: "immune".to_string(),
             groups: vec![
                 Group {
                     army: "immune".to_string(),
                     id: 1,
-                    units: Cell::new(17),
+                    units: 17,
                     unit_hp: 5390,
                     initiative: 2,
-                    attack: Attack::new(Fire, 4507),
-                    weaknesses: vec![Radiation, Bludgeoning],
+                    attack: Attack::new(FIRE, 4507),
+                    weaknesses: vec![RADIATION, BLUDGEONING],
                     immunities: vec![],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 2,
-                    units: Cell::new(989),
+                    units: 989,
                     unit_hp: 1274,
                     initiative: 3,
-                    attack: Attack::new(Slashing, 25),
-                    weaknesses: vec![Bludgeoning, Slashing],
-                    immunities: vec![Fire],
+                    attack: Attack::new(SLASHING, 25),
+                    weaknesses: vec![BLUDGEONING, SLASHING],
+                    immunities: vec![FIRE],
                 },
             ],
         }
     }
 
     fn test1_infection() -> Army {
-        use self::AttackKind::*;
-
         Army {
             name: "infection".to_string(),
             groups: vec![
                 Group {
                     army: "infection".to_string(),
                     id: 1,
-                    units: Cell::new(801),
+                    units: 801,
                     unit_hp: 4706,
                     initiative: 1,
-                    attack: Attack::new(Bludgeoning, 116),
-                    weaknesses: vec![Radiation],
+                    attack: Attack::new(BLUDGEONING, 116),
+                    weaknesses: vec![RADIATION],
                     immunities: vec![],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 2,
-                    units: Cell::new(4485),
+                    units: 4485,
                     unit_hp: 2961,
                     initiative: 4,
-                    attack: Attack::new(Slashing, 12),
-                    weaknesses: vec![Fire, Cold],
-                    immunities: vec![Radiation],
+                    attack: Attack::new(SLASHING, 12),
+                    weaknesses: vec![FIRE, COLD],
+                    immunities: vec![RADIATION],
                 },
             ],
         }
     }
 
-    fn real_immune() -> Army {
-        use self::AttackKind::*;
+    /// Two groups that are each immune to the other's attack, so neither
+    /// can ever win.
+    fn stalemate_immune() -> Army {
+        Army {
+            name: "immune".to_string(),
+            groups: vec![
+                Group {
+                    army: "immune".to_string(),
+                    id: 1,
+                    units: 10,
+                    unit_hp: 100,
+                    initiative: 2,
+                    attack: Attack::new(FIRE, 50),
+                    weaknesses: vec![],
+                    immunities: vec![COLD],
+                },
+            ],
+        }
+    }
+
+    fn stalemate_infection() -> Army {
+        Army {
+            name: "infection".to_string(),
+            groups: vec![
+                Group {
+                    army: "infection".to_string(),
+                    id: 1,
+                    units: 10,
+                    unit_hp: 100,
+                    initiative: 1,
+                    attack: Attack::new(COLD, 50),
+                    weaknesses: vec![],
+                    immunities: vec![FIRE],
+                },
+            ],
+        }
+    }
 
+    fn real_immune() -> Army {
         Army {
             name: "immune".to_string(),
             groups: vec![
                 Group {
                     army: "immune".to_string(),
                     id: 1,
-                    units: Cell::new(479),
+                    units: 479,
                     unit_hp: 3393,
                     initiative: 8,
-                    attack: Attack::new(Cold, 66),
-                    weaknesses: vec![Radiation],
+                    attack: Attack::new(COLD, 66),
+                    weaknesses: vec![RADIATION],
                     immunities: vec![],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 2,
-                    units: Cell::new(2202),
+                    units: 2202,
                     unit_hp: 4950,
                     initiative: 2,
-                    attack: Attack::new(Cold, 18),
-                    weaknesses: vec![Fire],
-                    immunities: vec![Slashing],
+                    attack: Attack::new(COLD, 18),
+                    weaknesses: vec![FIRE],
+                    immunities: vec![SLASHING],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 3,
-                    units: Cell::new(8132),
+                    units: 8132,
                     unit_hp: 9680,
                     initiative: 7,
-                    attack: Attack::new(Radiation, 9),
-                    weaknesses: vec![Bludgeoning, Fire],
-                    immunities: vec![Slashing],
+                    attack: Attack::new(RADIATION, 9),
+                    weaknesses: vec![BLUDGEONING, FIRE],
+                    immunities: vec![SLASHING],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 4,
-                    units: Cell::new(389),
+                    units: 389,
                     unit_hp: 13983,
                     initiative: 13,
-                    attack: Attack::new(Cold, 256),
+                    attack: Attack::new(COLD, 256),
                     weaknesses: vec![],
-                    immunities: vec![Bludgeoning],
+                    immunities: vec![BLUDGEONING],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 5,
-                    units: Cell::new(1827),
+                    units: 1827,
                     unit_hp: 5107,
                     initiative: 18,
-                    attack: Attack::new(Slashing, 24),
+                    attack: Attack::new(SLASHING, 24),
                     weaknesses: vec![],
                     immunities: vec![],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 6,
-                    units: Cell::new(7019),
+                    units: 7019,
                     unit_hp: 2261,
                     initiative: 16,
-                    attack: Attack::new(Fire, 3),
+                    attack: Attack::new(FIRE, 3),
                     weaknesses: vec![],
-                    immunities: vec![Radiation, Slashing, Cold],
+                    immunities: vec![RADIATION, SLASHING, COLD],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 7,
-                    units: Cell::new(4736),
+                    units: 4736,
                     unit_hp: 8421,
                     initiative: 3,
-                    attack: Attack::new(Slashing, 17),
-                    weaknesses: vec![Cold],
+                    attack: Attack::new(SLASHING, 17),
+                    weaknesses: vec![COLD],
                     immunities: vec![],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 8,
-                    units: Cell::new(491),
+                    units: 491,
                     unit_hp: 3518,
                     initiative: 1,
-                    attack: Attack::new(Radiation, 65),
-                    weaknesses: vec![Cold],
-                    immunities: vec![Fire, Bludgeoning],
+                    attack: Attack::new(RADIATION, 65),
+                    weaknesses: vec![COLD],
+                    immunities: vec![FIRE, BLUDGEONING],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 9,
-                    units: Cell::new(2309),
+                    units: 2309,
                     unit_hp: 7353,
                     initiative: 20,
-                    attack: Attack::new(Bludgeoning, 31),
+                    attack: Attack::new(BLUDGEONING, 31),
                     weaknesses: vec![],
-                    immunities: vec![Radiation],
+                    immunities: vec![RADIATION],
                 },
                 Group {
                     army: "immune".to_string(),
                     id: 10,
-                    units: Cell::new(411),
+                    units: 411,
                     unit_hp: 6375,
                     initiative: 14,
-                    attack: Attack::new(Bludgeoning, 151),
-                    weaknesses: vec![Cold, Fire],
-                    immunities: vec![Slashing],
+                    attack: Attack::new(BLUDGEONING, 151),
+                    weaknesses: vec![COLD, FIRE],
+                    immunities: vec![SLASHING],
                 },
             ],
         }
     }
 
     fn real_infection() -> Army {
-        use self::AttackKind::*;
-
         Army {
             name: "infection".to_string(),
             groups: vec![
                 Group {
                     army: "infection".to_string(),
                     id: 1,
-                    units: Cell::new(148),
+                    units: 148,
                     unit_hp: 31914,
                     initiative: 4,
-                    attack: Attack::new(Cold, 416),
-                    weaknesses: vec![Bludgeoning],
-                    immunities: vec![Radiation, Cold, Fire],
+                    attack: Attack::new(COLD, 416),
+                    weaknesses: vec![BLUDGEONING],
+                    immunities: vec![RADIATION, COLD, FIRE],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 2,
-                    units: Cell::new(864),
+                    units: 864,
                     unit_hp: 38189,
                     initiative: 6,
-                    attack: Attack::new(Slashing, 72),
+                    attack: Attack::new(SLASHING, 72),
                     weaknesses: vec![],
                     immunities: vec![],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 3,
-                    units: Cell::new(2981),
+                    units: 2981,
                     unit_hp: 7774,
                     initiative: 15,
-                    attack: Attack::new(Fire, 4),
+                    attack: Attack::new(FIRE, 4),
                     weaknesses: vec![],
-                    immunities: vec![Bludgeoning, Cold],
+                    immunities: vec![BLUDGEONING, COLD],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 4,
-                    units: Cell::new(5259),
+                    units: 5259,
                     unit_hp: 22892,
                     initiative: 5,
-                    attack: Attack::new(Fire, 8),
+                    attack: Attack::new(FIRE, 8),
                     weaknesses: vec![],
                     immunities: vec![],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 5,
-                    units: Cell::new(318),
+                    units: 318,
                     unit_hp: 16979,
                     initiative: 9,
-                    attack: Attack::new(Bludgeoning, 106),
-                    weaknesses: vec![Fire],
+                    attack: Attack::new(BLUDGEONING, 106),
+                    weaknesses: vec![FIRE],
                     immunities: vec![],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 6,
-                    units: Cell::new(5017),
+                    units: 5017,
                     unit_hp: 32175,
                     initiative: 17,
-                    attack: Attack::new(Bludgeoning, 11),
-                    weaknesses: vec![Slashing],
-                    immunities: vec![Radiation],
+                    attack: Attack::new(BLUDGEONING, 11),
+                    weaknesses: vec![SLASHING],
+                    immunities: vec![RADIATION],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 7,
-                    units: Cell::new(4308),
+                    units: 4308,
                     unit_hp: 14994,
                     initiative: 10,
-                    attack: Attack::new(Fire, 5),
-                    weaknesses: vec![Slashing],
-                    immunities: vec![Fire, Cold],
+                    attack: Attack::new(FIRE, 5),
+                    weaknesses: vec![SLASHING],
+                    immunities: vec![FIRE, COLD],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 8,
-                    units: Cell::new(208),
+                    units: 208,
                     unit_hp: 14322,
                     initiative: 19,
-                    attack: Attack::new(Cold, 133),
-                    weaknesses: vec![Radiation],
+                    attack: Attack::new(COLD, 133),
+                    weaknesses: vec![RADIATION],
                     immunities: vec![],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 9,
-                    units: Cell::new(3999),
+                    units: 3999,
                     unit_hp: 48994,
                     initiative: 11,
-                    attack: Attack::new(Cold, 20),
-                    weaknesses: vec![Cold, Slashing],
+                    attack: Attack::new(COLD, 20),
+                    weaknesses: vec![COLD, SLASHING],
                     immunities: vec![],
                 },
                 Group {
                     army: "infection".to_string(),
                     id: 10,
-                    units: Cell::new(1922),
+                    units: 1922,
                     unit_hp: 34406,
                     initiative: 12,
-                    attack: Attack::new(Slashing, 35),
-                    weaknesses: vec![Slashing],
+                    attack: Attack::new(SLASHING, 35),
+                    weaknesses: vec![SLASHING],
                     immunities: vec![],
                 },
             ],
@@ -0,0 +1,359 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+
+macro_rules! err {
+    ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
+}
+
+pub type Result<T> = result::Result<T, Box<Error>>;
+
+/// Parse a list of points, one per line. Blank lines and whitespace around
+/// each coordinate are ignored.
+///
+/// Points may have any number of dimensions, but every point must have the
+/// same number. Every line that fails is reported in the error, not just the
+/// first one.
+pub fn parse_points(s: &str) -> Result<Vec<Point>> {
+    let mut points: Vec<Point> = vec![];
+    let mut problems = vec![];
+    for (i, line) in s.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let point: Point = match line.parse() {
+            Ok(point) => point,
+            Err(err) => {
+                problems.push(format!(
+                    "line {}: failed to parse {:?}: {}", i + 1, line, err,
+                ));
+                continue;
+            }
+        };
+        if let Some(first) = points.first() {
+            if point.dimensions() != first.dimensions() {
+                problems.push(format!(
+                    "line {}: point has {} dimensions, but earlier points \
+                     have {}",
+                    i + 1, point.dimensions(), first.dimensions(),
+                ));
+                continue;
+            }
+        }
+        points.push(point);
+    }
+    if !problems.is_empty() {
+        return err!("invalid points: {}", problems.join("; "));
+    }
+    Ok(points)
+}
+
+/// The largest distance between two points in the same constellation.
+pub const MAX_DISTANCE: i32 = 3;
+
+/// Returns the number of constellations formed by the given points.
+pub fn count_constellations(points: &[Point]) -> usize {
+    count_constellations_within(points, MAX_DISTANCE)
+}
+
+/// Returns the number of constellations formed by the given points, where
+/// two points are joined when their distance is at most `max_distance`.
+///
+/// All of the points must have the same number of dimensions.
+///
+/// Unlike adding each point to `Constellations`, this finds the pairs of
+/// points within range of each other in parallel, and then joins them on
+/// the current thread. Joining is cheap compared to finding the pairs, and
+/// the count doesn't depend on the order in which pairs are joined.
+pub fn count_constellations_within(
+    points: &[Point],
+    max_distance: i32,
+) -> usize {
+    // See Constellations::grid for why only adjacent buckets are checked.
+    let side = cmp::max(1, max_distance + 1);
+    let dimensions = points.first().map_or(0, Point::dimensions);
+    let bucketed = buckets_pay(dimensions, points.len());
+    let mut grid: HashMap<Vec<i32>, Vec<usize>> = HashMap::new();
+    if bucketed {
+        for (i, p) in points.iter().enumerate() {
+            grid.entry(p.bucket(side)).or_default().push(i);
+        }
+    }
+
+    let edges: Vec<Vec<(usize, usize)>> = (0..points.len())
+        .into_par_iter()
+        .fold(Vec::new, |mut edges, i| {
+            let p = &points[i];
+            let mut check = |j: usize| {
+                if i < j && p.distance(&points[j]) <= max_distance {
+                    edges.push((i, j));
+                }
+            };
+            if bucketed {
+                for_each_neighbor(&p.bucket(side), |key| {
+                    grid.get(key).into_iter().flatten().for_each(|&j| {
+                        check(j)
+                    });
+                });
+            } else {
+                (0..points.len()).for_each(check);
+            }
+            edges
+        })
+        .collect();
+
+    let mut sets = DisjointSet::new(points.len());
+    for &(i, j) in edges.iter().flatten() {
+        sets.union(i, j);
+    }
+    sets.len()
+}
+
+/// Returns the number of buckets adjacent to any one bucket (including
+/// itself) in a grid with the given number of dimensions, or `None` if that
+/// doesn't fit in a `usize`.
+fn neighbor_count(dimensions: usize) -> Option<usize> {
+    let mut count: usize = 1;
+    for _ in 0..dimensions {
+        count = count.checked_mul(3)?;
+    }
+    Some(count)
+}
+
+/// Returns true if looking up each point's neighboring buckets is cheaper
+/// than comparing it with all of the given number of points.
+///
+/// The number of neighboring buckets grows exponentially with the number of
+/// dimensions, so for points with many dimensions, comparing every pair is
+/// faster. (And with enough dimensions, the number of neighbors doesn't
+/// even fit in a `usize`.)
+fn buckets_pay(dimensions: usize, points: usize) -> bool {
+    match neighbor_count(dimensions) {
+        None => false,
+        Some(n) => n <= points,
+    }
+}
+
+/// Call `f` with the key of every bucket adjacent to the given one,
+/// including itself.
+///
+/// This panics if there are too many adjacent buckets to count. Callers
+/// should check `buckets_pay` first.
+fn for_each_neighbor(bucket: &[i32], mut f: impl FnMut(&[i32])) {
+    let count = neighbor_count(bucket.len()).expect("too many dimensions");
+    let mut key = vec![0; bucket.len()];
+    for mut d in 0..count {
+        // Each of the 3^dimensions neighbors is an offset of -1, 0 or 1 in
+        // each dimension, given by the base 3 digits of `d`.
+        for (k, &c) in key.iter_mut().zip(bucket) {
+            *k = c + (d % 3) as i32 - 1;
+            d /= 3;
+        }
+        f(&key);
+    }
+}
+
+/// A growing set of points, grouped into constellations as they're added.
+#[derive(Clone, Debug)]
+pub struct Constellations {
+    max_distance: i32,
+    /// The side length of each hypercube in `grid`.
+    side: i32,
+    points: Vec<Point>,
+    /// The indices of the points in each hypercube of the grid, keyed by
+    /// `Point::bucket`.
+    ///
+    /// Only points within `max_distance` of each other can be joined, and
+    /// each of their coordinates can differ by at most that much. So if we
+    /// bucket points into a grid of hypercubes just wider than that, we only
+    /// need to compare each point with the points in its own bucket and
+    /// the buckets adjacent to it.
+    grid: HashMap<Vec<i32>, Vec<usize>>,
+    sets: DisjointSet,
+}
+
+/// What happened when a point was added to a set of constellations.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MergeInfo {
+    /// The number of existing constellations the point joined. When this is
+    /// zero, the point forms a new constellation by itself. When it's more
+    /// than one, the point merged them all into a single constellation.
+    pub joined: usize,
+}
+
+impl Constellations {
+    /// Create an empty set of constellations, where two points are joined
+    /// when their distance is at most `max_distance`.
+    pub fn new(max_distance: i32) -> Constellations {
+        Constellations {
+            max_distance,
+            side: cmp::max(1, max_distance + 1),
+            points: vec![],
+            grid: HashMap::new(),
+            sets: DisjointSet::new(0),
+        }
+    }
+
+    /// Returns the number of constellations.
+    pub fn count(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// Add a point, joining it with every constellation that has a point
+    /// within range of it.
+    ///
+    /// This panics if the point doesn't have the same number of dimensions
+    /// as the points already added.
+    pub fn add_point(&mut self, p: Point) -> MergeInfo {
+        if let Some(first) = self.points.first() {
+            assert_eq!(
+                p.dimensions(), first.dimensions(),
+                "every point must have the same number of dimensions",
+            );
+        }
+        let i = self.sets.push();
+        let b = p.bucket(self.side);
+        let mut joined = 0;
+        let (grid, points, sets) = (&self.grid, &self.points, &mut self.sets);
+        let max_distance = self.max_distance;
+        let mut check = |j: usize| {
+            let near = p.distance(&points[j]) <= max_distance;
+            if near && sets.union(i, j) {
+                joined += 1;
+            }
+        };
+        if buckets_pay(p.dimensions(), points.len()) {
+            for_each_neighbor(&b, |key| {
+                grid.get(key).into_iter().flatten().for_each(|&j| check(j));
+            });
+        } else {
+            (0..points.len()).for_each(check);
+        }
+        self.grid.entry(b).or_default().push(i);
+        self.points.push(p);
+        MergeInfo { joined }
+    }
+}
+
+/// A union-find structure over the indices `0..n`, where each set is a
+/// constellation.
+#[derive(Clone, Debug)]
+pub struct DisjointSet {
+    /// The parent of each index. Roots are their own parent.
+    parents: Vec<usize>,
+    /// An upper bound on the height of the tree rooted at each index. Only
+    /// meaningful for roots.
+    ranks: Vec<u8>,
+    /// The number of distinct sets.
+    len: usize,
+}
+
+impl DisjointSet {
+    /// Create a new set of `n` singleton sets.
+    pub fn new(n: usize) -> DisjointSet {
+        DisjointSet { parents: (0..n).collect(), ranks: vec![0; n], len: n }
+    }
+
+    /// Add a new singleton set, and return its index.
+    pub fn push(&mut self) -> usize {
+        let i = self.parents.len();
+        self.parents.push(i);
+        self.ranks.push(0);
+        self.len += 1;
+        i
+    }
+
+    /// Returns the number of distinct sets.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if and only if there are no sets at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the representative of the set containing `i`.
+    pub fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            // Path halving: point every other node at its grandparent.
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    /// Merge the sets containing `i1` and `i2`, and return whether they
+    /// were distinct sets beforehand.
+    pub fn union(&mut self, i1: usize, i2: usize) -> bool {
+        let (r1, r2) = (self.find(i1), self.find(i2));
+        if r1 == r2 {
+            return false;
+        }
+        if self.ranks[r1] < self.ranks[r2] {
+            self.parents[r1] = r2;
+        } else {
+            self.parents[r2] = r1;
+            if self.ranks[r1] == self.ranks[r2] {
+                self.ranks[r1] += 1;
+            }
+        }
+        self.len -= 1;
+        true
+    }
+}
+
+/// A point with any number of dimensions. The puzzle only uses four.
+#[derive(Clone, Debug)]
+pub struct Point {
+    pub coords: Vec<i32>,
+}
+
+impl Point {
+    /// Returns the number of coordinates in this point.
+    pub fn dimensions(&self) -> usize {
+        self.coords.len()
+    }
+
+    /// Returns the grid cell containing this point, for a grid of hypercubes
+    /// with the given side length.
+    fn bucket(&self, side: i32) -> Vec<i32> {
+        self.coords.iter().map(|c| c.div_euclid(side)).collect()
+    }
+
+    /// Returns the Manhattan distance between two points, which must have
+    /// the same number of dimensions.
+    pub fn distance(&self, other: &Point) -> i32 {
+        assert_eq!(self.dimensions(), other.dimensions());
+        self.coords.iter().zip(&other.coords).map(|(a, b)| (a - b).abs()).sum()
+    }
+}
+
+impl FromStr for Point {
+    type Err = Box<Error>;
+
+    fn from_str(s: &str) -> Result<Point> {
+        let mut coords = vec![];
+        for part in s.trim().split(",") {
+            coords.push(part.trim().parse()?);
+        }
+        Ok(Point { coords })
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, c) in self.coords.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
@@ -1,129 +1,127 @@
+use std::env;
 use std::error::Error;
-use std::i32;
+use std::ffi::OsString;
+use std::fs;
 use std::io::{self, Read, Write};
-use std::result;
-use std::str::{self, FromStr};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use aoc25::{
+    count_constellations_within, parse_points, Constellations, Point, Result,
+    MAX_DISTANCE,
+};
 
 macro_rules! err {
     ($($tt:tt)*) => { Err(Box::<Error>::from(format!($($tt)*))) }
 }
 
-type Result<T> = result::Result<T, Box<Error>>;
-
 fn main() -> Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
-
-    let mut points: Vec<Point> = vec![];
-    for line in input.lines() {
-        let point = line.parse().or_else(|err| {
-            err!("failed to parse '{:?}': {}", line, err)
-        })?;
-        points.push(point);
+    let args = Args::parse()?;
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
     }
+    let input = match args.input {
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        Some(ref path) => fs::read_to_string(path)?,
+    };
 
-    part1(&points)?;
-    Ok(())
-}
-
-fn part1(points: &[Point]) -> Result<()> {
-    let mut consts = Constellations::shatter_all(points);
-    while consts.step() {}
-    writeln!(io::stdout(), "constellations: {}", consts.groups.len())?;
+    let points = parse_points(&input)?;
+    part1(&points, &args)?;
     Ok(())
 }
 
-#[derive(Clone, Debug)]
-struct Constellations {
-    groups: Vec<Constellation>,
-}
-
-impl Constellations {
-    fn shatter_all(points: &[Point]) -> Constellations {
-        let mut groups = vec![];
-        for &p in points {
-            groups.push(Constellation { points: vec![p] });
-        }
-        Constellations { groups }
-    }
-
-    fn step(&mut self) -> bool {
-        for i in 0..self.groups.len() {
-            for j in i+1..self.groups.len() {
-                if self.groups[i].is_connected(&self.groups[j]) {
-                    self.merge(i, j);
-                    return true;
-                }
-            }
+fn part1(points: &[Point], args: &Args) -> Result<()> {
+    let start = Instant::now();
+    let count = if args.stream {
+        let mut constellations = Constellations::new(args.distance);
+        for p in points {
+            let info = constellations.add_point(p.clone());
+            writeln!(
+                io::stdout(),
+                "{}: joined {}, constellations: {}",
+                p, info.joined, constellations.count(),
+            )?;
         }
-        false
-    }
-
-    fn merge(&mut self, i1: usize, i2: usize) {
-        let g2 = self.groups.swap_remove(i2);
-        self.groups[i1].join(&g2);
+        constellations.count()
+    } else {
+        count_constellations_within(points, args.distance)
+    };
+    writeln!(io::stdout(), "constellations: {}", count)?;
+    if args.time {
+        writeln!(
+            io::stdout(),
+            "counting took {:?} with {} threads",
+            start.elapsed(), rayon::current_num_threads(),
+        )?;
     }
+    Ok(())
 }
 
 #[derive(Clone, Debug)]
-struct Constellation {
-    points: Vec<Point>,
+struct Args {
+    /// A file containing the puzzle input. When absent, stdin is read.
+    input: Option<PathBuf>,
+    /// The largest distance between two points in the same constellation.
+    distance: i32,
+    /// Whether to print the number of constellations after each point is
+    /// added.
+    stream: bool,
+    /// Whether to print how long counting took.
+    time: bool,
+    /// The number of threads to count with. When absent, rayon picks.
+    threads: Option<usize>,
 }
 
-impl Constellation {
-    fn join(&mut self, other: &Constellation) {
-        self.points.extend(other.points.iter().cloned());
-    }
-
-    fn is_connected(&self, other: &Constellation) -> bool {
-        for p in other.points.iter() {
-            if self.is_point_connected(p) {
-                return true;
-            }
-        }
-        false
-    }
-
-    fn is_point_connected(&self, point: &Point) -> bool {
-        for p in self.points.iter() {
-            if point.distance(p) <= 3 {
-                return true;
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut args = Args {
+            input: None,
+            distance: MAX_DISTANCE,
+            stream: false,
+            time: false,
+            threads: None,
+        };
+        let mut it = env::args_os().skip(1);
+        while let Some(arg) = it.next() {
+            if arg == "--distance" {
+                args.distance = flag_value(&mut it, "--distance")?.parse()?;
+                if args.distance < 0 {
+                    return err!("--distance must not be negative");
+                }
+            } else if arg == "--stream" {
+                args.stream = true;
+            } else if arg == "--time" {
+                args.time = true;
+            } else if arg == "--threads" {
+                let threads = flag_value(&mut it, "--threads")?.parse()?;
+                if threads == 0 {
+                    return err!("--threads must be at least 1");
+                }
+                args.threads = Some(threads);
+            } else if arg.to_string_lossy().starts_with("--") {
+                return err!("unrecognized flag: {:?}", arg);
+            } else if args.input.is_none() {
+                args.input = Some(PathBuf::from(arg));
+            } else {
+                return err!("unexpected argument: {:?}", arg);
             }
         }
-        false
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
-struct Point {
-    x: i32,
-    y: i32,
-    z: i32,
-    t: i32,
-}
-
-impl Point {
-    fn distance(&self, other: &Point) -> i32 {
-        (self.x - other.x).abs()
-        + (self.y - other.y).abs()
-        + (self.z - other.z).abs()
-        + (self.t - other.t).abs()
+        Ok(args)
     }
 }
 
-impl FromStr for Point {
-    type Err = Box<Error>;
-
-    fn from_str(s: &str) -> Result<Point> {
-        let parts: Vec<&str> = s.trim().split(",").collect();
-        if parts.len() != 4 {
-            return err!("unrecognized point '{:?}'", s);
-        }
-        Ok(Point {
-            x: parts[0].parse()?,
-            y: parts[1].parse()?,
-            z: parts[2].parse()?,
-            t: parts[3].parse()?,
-        })
+/// Returns the value following the given flag, which must be valid UTF-8.
+fn flag_value(
+    it: &mut impl Iterator<Item = OsString>,
+    flag: &str,
+) -> Result<String> {
+    match it.next().map(|value| value.into_string()) {
+        None => err!("{} requires a value", flag),
+        Some(Err(value)) => err!("invalid value for {}: {:?}", flag, value),
+        Some(Ok(value)) => Ok(value),
     }
 }